@@ -1,46 +1,31 @@
 use adventofcode2019::build_main_res;
 use adventofcode2019::intcode::cpu::{parse_code, CPU};
-use adventofcode2019::intcode::io::IOQueues;
+use adventofcode2019::intcode::pipeline::Pipeline;
 use adventofcode2019::intcode::IntcodeError::LogicError;
-use adventofcode2019::intcode::IntcodeState::Halted;
-use adventofcode2019::intcode::{IOWrapper, IntcodeResult, Runnable};
+use adventofcode2019::intcode::{IntcodeResult, Runnable};
 use itertools::Itertools;
 
-type System = IOWrapper<IOQueues, CPU>;
+fn to_thrusters(program: &[isize], phases: Vec<isize>, feedback: bool) -> IntcodeResult<isize> {
+    let amps = phases.iter().map(|_| CPU::new(program.to_vec())).collect_vec();
+    let mut pipeline = Pipeline::new(amps);
+    if feedback {
+        pipeline = pipeline.with_feedback();
+    }
+
+    for (i, phase) in phases.into_iter().enumerate() {
+        pipeline.feed(i, phase);
+    }
+    pipeline.accept_input(0)?;
+    pipeline.run()?;
 
-fn init_thrusters(program: &Vec<isize>, phases: Vec<isize>) -> IntcodeResult<Vec<System>> {
-    phases.into_iter().map(|x| {
-        let io = IOQueues::new();
-        let cpu = CPU::new(program.clone());
-        let mut system = cpu.wrap(io);
-        system.accept_input(x)?;
-        Ok(system)
-    }).collect()
+    pipeline.outputs.last().copied().ok_or(LogicError("Expected an output".to_string()))
 }
 
 fn part1(input: &str) -> IntcodeResult<isize> {
-    let ref program = parse_code(input)?;
-
-    let to_thrusters = |phases: Vec<isize>| -> IntcodeResult<isize> {
-        let mut systems = init_thrusters(program, phases)?;
-        systems[0].accept_input(0)?;
-
-        for i in 0..4 {
-            systems[i].run_until_output()?;
-            let msg = format!("Expected an output for intcode {i}");
-            let output = systems[i].outer.output
-                .pop_front()
-                .ok_or(LogicError(msg))?;
-
-            systems[i+1].accept_input(output)?;
-        }
-
-        systems[4].run_until_output()?;
-        systems[4].outer.output.pop_front().ok_or(LogicError("Expected an output".to_string()))
-    };
+    let program = &parse_code(input)?;
 
     let results = (0..5).permutations(5)
-        .map(to_thrusters)
+        .map(|phases| to_thrusters(program, phases, false))
         .collect::<IntcodeResult<Vec<isize>>>()?;
 
     results.into_iter()
@@ -49,36 +34,10 @@ fn part1(input: &str) -> IntcodeResult<isize> {
 }
 
 fn part2(input: &str) -> IntcodeResult<isize> {
-    let ref program = parse_code(input)?;
-
-    let to_thrusters = |phases: Vec<isize>| -> IntcodeResult<isize> {
-        let mut systems = init_thrusters(program, phases)?;
-        systems[0].accept_input(0)?;
-
-        let mut last = 0;
-
-        loop {
-            let steps = systems.iter_mut()
-                .map(|c| c.step())
-                .collect::<IntcodeResult<Vec<_>>>()?;
-
-            if steps.iter().all(|s| *s == Halted) { return Ok(last) };
-
-            for i in 0..5 {
-                while let Some(o) = systems[i].outer.output.pop_front() {
-                    let j = (i + 1) % 5;
-                    systems[j].accept_input(o)?;
-
-                    if i == 4 {
-                        last = o;
-                    }
-                }
-            }
-        }
-    };
+    let program = &parse_code(input)?;
 
     let results = (5..10).permutations(5)
-        .map(to_thrusters)
+        .map(|phases| to_thrusters(program, phases, true))
         .collect::<IntcodeResult<Vec<isize>>>()?;
 
     results.into_iter()
@@ -1,11 +1,10 @@
 use adventofcode2019::build_main_res;
 use adventofcode2019::intcode::cpu::CPU;
-use adventofcode2019::intcode::io::{IProvider, OProvider};
-use adventofcode2019::intcode::IntcodeError::LogicError;
-use adventofcode2019::intcode::IntcodeState::Continue;
-use adventofcode2019::intcode::{IntcodeResult, IntcodeState, Runnable};
+use adventofcode2019::intcode::io::{for_each, from_fn};
+use adventofcode2019::intcode::{IntcodeResult, Runnable};
 use adventofcode2019::points::Point2D;
 use itertools::{chain, Itertools};
+use std::cell::RefCell;
 use std::cmp::{max, min};
 use std::collections::HashSet;
 
@@ -26,29 +25,12 @@ impl Robot {
         let awaiting_color = true;
         Robot { position, direction, is_white, visited, awaiting_color }
     }
-}
-
-impl IProvider for Robot {
-    type PInput = isize;
-    type RInput = isize;
 
-    fn provide_input<O>(&mut self) -> IntcodeResult<(IntcodeState<O>, Option<isize>)> {
-        match self.is_white.contains(&self.position) {
-            true => Ok((Continue, Some(1))),
-            false => Ok((Continue, Some(0)))
-        }
+    fn color(&self) -> isize {
+        if self.is_white.contains(&self.position) { 1 } else { 0 }
     }
 
-    fn receive_input(&mut self, input: Self::RInput) -> IntcodeResult<()> {
-        Err(LogicError(format!("Didn't expect to receive any input; received {input}")))
-    }
-}
-
-impl OProvider for Robot {
-    type POutput = isize;
-    type ROutput = isize;
-
-    fn handle_output(&mut self, output: Self::ROutput) -> IntcodeResult<IntcodeState<isize>> {
+    fn handle_output(&mut self, output: isize) {
         if self.awaiting_color {
             if output == 1 {
                 self.is_white.insert(self.position);
@@ -57,7 +39,6 @@ impl OProvider for Robot {
                 self.is_white.remove(&self.position);
             }
             self.awaiting_color = false;
-            Ok(Continue)
         }
         else {
             let Point2D(x, y) = self.direction;
@@ -65,27 +46,28 @@ impl OProvider for Robot {
             self.position = self.position + self.direction;
             self.visited.insert(self.position);
             self.awaiting_color = true;
-            Ok(Continue)
         }
     }
+
+    fn paint(self, input: &str) -> IntcodeResult<Robot> {
+        let robot = RefCell::new(self);
+        let cpu = CPU::parse(input)?;
+        cpu.connect(from_fn(|| robot.borrow().color()), for_each(|o| robot.borrow_mut().handle_output(o))).run()?;
+        Ok(robot.into_inner())
+    }
 }
 
 fn part1(input: &str) -> IntcodeResult<usize> {
-    let robot = Robot::new();
-    let cpu = CPU::parse(input)?;
-    let mut system = cpu.wrap(robot);
-    system.run()?;
-    Ok(system.outer.visited.len())
+    let robot = Robot::new().paint(input)?;
+    Ok(robot.visited.len())
 }
 
 fn part2(input: &str) -> IntcodeResult<String> {
     let mut robot = Robot::new();
     robot.is_white.insert(Point2D(0, 0));
-    let cpu = CPU::parse(input)?;
-    let mut system = cpu.wrap(robot);
-    system.run()?;
+    let robot = robot.paint(input)?;
 
-    let (x_min, x_max, y_min, y_max) = system.outer.is_white.iter()
+    let (x_min, x_max, y_min, y_max) = robot.is_white.iter()
         .fold((isize::MAX, isize::MIN, isize::MAX, isize::MIN),
               |(x0, x1, y0, y1), &Point2D(x, y)| {
                   (min(x0, x), max(x1, x), min(y0, y), max(y1, y))
@@ -97,7 +79,7 @@ fn part2(input: &str) -> IntcodeResult<String> {
         (y_max - y_min + 1) as usize
     ];
 
-    for &Point2D(x, y) in system.outer.is_white.iter() {
+    for &Point2D(x, y) in robot.is_white.iter() {
         let i = (y_max - y) as usize;
         let j = (x - x_min) as usize;
         message[i][j] = '#';
@@ -1,11 +1,10 @@
 use crate::GameObject::{Ball, Block, Empty, HorizontalPaddle, Wall};
 use adventofcode2019::build_main_res;
 use adventofcode2019::intcode::cpu::CPU;
-use adventofcode2019::intcode::io::{IProvider, OProvider};
-use adventofcode2019::intcode::IntcodeError::LogicError;
-use adventofcode2019::intcode::IntcodeState::Continue;
-use adventofcode2019::intcode::{IntcodeResult, IntcodeState, Runnable};
+use adventofcode2019::intcode::io::{for_each, from_fn};
+use adventofcode2019::intcode::{IntcodeResult, Runnable};
 use adventofcode2019::points::Point2D;
+use std::cell::RefCell;
 use std::collections::HashMap;
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -47,26 +46,12 @@ impl ArcadeCabinet {
         let output_cache = Vec::with_capacity(3);
         ArcadeCabinet { screen, score, ball, paddle, output_cache }
     }
-}
-
-impl IProvider for ArcadeCabinet {
-    type PInput = isize;
-    type RInput = isize;
 
-    fn provide_input<O>(&mut self) -> IntcodeResult<(IntcodeState<O>, Option<isize>)> {
-        Ok((Continue, Some((self.ball.0 - self.paddle.0).signum())))
+    fn joystick(&self) -> isize {
+        (self.ball.0 - self.paddle.0).signum()
     }
 
-    fn receive_input(&mut self, input: isize) -> IntcodeResult<()> {
-        Err(LogicError(format!("Didn't expect to receive any input; received {input}")))
-    }
-}
-
-impl OProvider for ArcadeCabinet {
-    type POutput = isize;
-    type ROutput = isize;
-
-    fn handle_output(&mut self, output: isize) -> IntcodeResult<IntcodeState<isize>> {
+    fn handle_output(&mut self, output: isize) {
         self.output_cache.push(output);
 
         if self.output_cache.len() == 3 {
@@ -94,18 +79,19 @@ impl OProvider for ArcadeCabinet {
 
             self.output_cache.clear();
         }
+    }
 
-        Ok(Continue)
+    fn play(self, cpu: CPU) -> IntcodeResult<ArcadeCabinet> {
+        let cabinet = RefCell::new(self);
+        cpu.connect(from_fn(|| cabinet.borrow().joystick()), for_each(|o| cabinet.borrow_mut().handle_output(o))).run()?;
+        Ok(cabinet.into_inner())
     }
 }
 
 fn part1(input: &str) -> IntcodeResult<usize> {
-    let cabinet = ArcadeCabinet::new();
-    let cpu = CPU::parse(input)?;
-    let mut system = cpu.wrap(cabinet);
-    system.run()?;
+    let cabinet = ArcadeCabinet::new().play(CPU::parse(input)?)?;
 
-    let result = system.outer.screen.values()
+    let result = cabinet.screen.values()
         .filter(|&&v| v == Block)
         .count();
 
@@ -113,12 +99,10 @@ fn part1(input: &str) -> IntcodeResult<usize> {
 }
 
 fn part2(input: &str) -> IntcodeResult<isize> {
-    let cabinet = ArcadeCabinet::new();
     let mut cpu = CPU::parse(input)?;
-    cpu.memory.set(0, 2);
-    let mut system = cpu.wrap(cabinet);
-    system.run()?;
-    Ok(system.outer.score)
+    cpu.memory.set(0, 2)?;
+    let cabinet = ArcadeCabinet::new().play(cpu)?;
+    Ok(cabinet.score)
 }
 
 build_main_res!("day13.txt", "Part 1" => part1, "Part 2" => part2);
\ No newline at end of file
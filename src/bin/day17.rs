@@ -1,5 +1,5 @@
 use adventofcode2019::intcode::IntcodeError::{ExpectedOutput, LogicError};
-use adventofcode2019::intcode::{IOWrapper, IntcodeResult, IntcodeState, Runnable};
+use adventofcode2019::intcode::{IntcodeResult, Runnable};
 use adventofcode2019::grid::Direction::{Down, Left, Right, Up};
 use adventofcode2019::grid::{Direction, Position};
 use adventofcode2019::build_main_res;
@@ -8,8 +8,7 @@ use std::cmp::min;
 use std::collections::{HashSet, VecDeque};
 use std::iter::once;
 use adventofcode2019::intcode::cpu::CPU;
-use adventofcode2019::intcode::IntcodeState::Continue;
-use adventofcode2019::intcode::io::{Bus, IProvider, Last, OProvider};
+use adventofcode2019::intcode::ascii::AsciiIO;
 
 struct Scene {
     scaffolds: HashSet<Position>,
@@ -17,82 +16,33 @@ struct Scene {
     robot_dir: Direction
 }
 
-struct SceneBuilder {
-    i: usize,
-    j: usize,
-    scaffolds: HashSet<Position>,
-    robot_pos: Option<Position>,
-    robot_dir: Option<Direction>
-}
-
-impl SceneBuilder {
-    fn new() -> SceneBuilder {
-        SceneBuilder {
-            i: 0,
-            j: 0,
-            scaffolds: HashSet::new(),
-            robot_pos: None,
-            robot_dir: None
-        }
-    }
-
-    fn build(self) -> IntcodeResult<Scene> {
-        let robot_pos = self.robot_pos
-            .ok_or(LogicError("Expected robot position".to_string()))?;
-
-        let robot_dir = self.robot_dir
-            .ok_or(LogicError("Expected robot direction".to_string()))?;
-
-        Ok(Scene { scaffolds: self.scaffolds, robot_pos, robot_dir })
-    }
-}
-
-impl IProvider for SceneBuilder {
-    type PInput = isize;
-    type RInput = ();
-
-    fn provide_input<O>(&mut self) -> IntcodeResult<(IntcodeState<O>, Option<isize>)> {
-        Err(LogicError("Didn't expect to be asked for any input".to_owned()))
-    }
-
-    fn receive_input(&mut self, input: Self::RInput) -> IntcodeResult<()> {
-        Err(LogicError(format!("Didn't expect to receive input; got {input:?}")))
-    }
-}
-
-impl OProvider for SceneBuilder {
-    type POutput = ();
-    type ROutput = isize;
-
-
-    fn handle_output(&mut self, output: Self::ROutput) -> IntcodeResult<IntcodeState<Self::POutput>> {
-        let c = (output as u8) as char;
-        match c {
-            '#' | '^' | '>' | 'v' | '<' => { self.scaffolds.insert(Position(self.i, self.j)); },
-            _ => ()
-        }
-
-        match c {
-            '^' | '>' | 'v' | '<' => { self.robot_pos = Some(Position(self.i, self.j)); },
-            _ => ()
+impl Scene {
+    fn parse(lines: &[String]) -> IntcodeResult<Scene> {
+        let mut scaffolds = HashSet::new();
+        let mut robot = None;
+
+        for (i, line) in lines.iter().enumerate() {
+            for (j, c) in line.chars().enumerate() {
+                let dir = match c {
+                    '^' => Some(Up),
+                    '>' => Some(Right),
+                    'v' => Some(Down),
+                    '<' => Some(Left),
+                    '#' => None,
+                    _ => continue
+                };
+
+                scaffolds.insert(Position(i, j));
+                if let Some(dir) = dir {
+                    robot = Some((Position(i, j), dir));
+                }
+            }
         }
 
-        match c {
-            '^' => { self.robot_dir = Some(Up); },
-            '>' => { self.robot_dir = Some(Right); },
-            'v' => { self.robot_dir = Some(Down); },
-            '<' => { self.robot_dir = Some(Left); }
-            _ => ()
-        }
+        let (robot_pos, robot_dir) = robot
+            .ok_or(LogicError("Expected robot position and direction".to_string()))?;
 
-        if c == '\n' {
-            self.i += 1;
-            self.j = 0;
-        } else {
-            self.j += 1;
-        }
-
-        Ok(Continue)
+        Ok(Scene { scaffolds, robot_pos, robot_dir })
     }
 }
 
@@ -253,12 +203,14 @@ impl Scene {
     }
 }
 
-fn part1(input: &str) -> IntcodeResult<usize> {
-    let io = SceneBuilder::new();
-    let cpu = CPU::parse(input)?;
-    let mut system = IOWrapper { outer: io, inner: cpu};
+fn scene(input: &str) -> IntcodeResult<Scene> {
+    let mut system = CPU::parse(input)?.wrap(AsciiIO::new());
     system.run()?;
-    let scene = system.outer.build()?;
+    Scene::parse(&system.outer.lines)
+}
+
+fn part1(input: &str) -> IntcodeResult<usize> {
+    let scene = scene(input)?;
 
     let intersections = scene.intersections();
 
@@ -270,19 +222,13 @@ fn part1(input: &str) -> IntcodeResult<usize> {
 }
 
 fn part2(input: &str) -> IntcodeResult<isize> {
-    let io = SceneBuilder::new();
-    let cpu = CPU::parse(input)?;
-    let mut system = IOWrapper { outer: io, inner: cpu};
-    system.run()?;
-    let scene = system.outer.build()?;
+    let scene = scene(input)?;
 
     let programs = scene.programs();
 
-    let mut input_chars = VecDeque::new();
-    Itertools::intersperse(programs.program.iter().cloned(), ',')
-        .for_each(|c| input_chars.push_back(c));
+    let mut commands = Itertools::intersperse(programs.program.iter().cloned(), ',').collect::<String>();
+    commands.push('\n');
 
-    input_chars.push_back('\n');
     for prog in [&programs.a, &programs.b, &programs.c] {
         let s = Itertools::intersperse(
             prog.as_ref().unwrap().iter()
@@ -296,22 +242,18 @@ fn part2(input: &str) -> IntcodeResult<isize> {
             ",".to_string()
         ).collect::<String>();
 
-        input_chars.extend(s.chars());
-        input_chars.push_back('\n');
+        commands.push_str(&s);
+        commands.push('\n');
     }
 
-    input_chars.push_back('n');
-    input_chars.push_back('\n');
+    commands.push_str("n\n");
 
-    let output = Last(None);
-    let io2 = Bus { input: input_chars, output };
-
-    let mut cpu2 = CPU::parse(input)?;
-    cpu2.memory.set(0, 2);
-    let mut system2 = IOWrapper { outer: io2, inner: cpu2 };
-    system2.run()?;
+    let mut cpu = CPU::parse(input)?;
+    cpu.memory.set(0, 2)?;
+    let mut system = cpu.wrap(AsciiIO::new().with_input(&commands)?);
+    system.run()?;
 
-    system2.outer.output.0.ok_or(ExpectedOutput)
+    system.outer.values.last().copied().ok_or(ExpectedOutput)
 }
 
 build_main_res!("day17.txt", "Part 1" => part1, "Part 2" => part2);
\ No newline at end of file
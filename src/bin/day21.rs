@@ -1,42 +1,27 @@
-use std::collections::VecDeque;
 use adventofcode2019::build_main_res;
-use adventofcode2019::intcode::{IntcodeResult, Runnable};
-use adventofcode2019::intcode::cpu::CPU;
-use adventofcode2019::intcode::IntcodeError::{ExpectedOutput, LogicError};
-use adventofcode2019::intcode::io::Bus;
+use adventofcode2019::intcode::IntcodeResult;
+use adventofcode2019::intcode::cpu::parse_code;
+use adventofcode2019::intcode::springdroid::{survey, Report};
+use adventofcode2019::intcode::springscript::{compile_str, Mode, Springscript};
+use adventofcode2019::intcode::IntcodeError::LogicError;
+
+// Runs a springscript program, failing with the hull the droid fell into if it doesn't make it.
+fn run(input: &str, script: &Springscript) -> IntcodeResult<isize> {
+    match survey(&parse_code(input)?, script)? {
+        Report::Damage(damage) => Ok(damage),
+        Report::Fell(hull) => Err(LogicError(format!("Fell into a hole in {hull}")))
+    }
+}
 
 /*
    We want to jump if the space 4 in front of us is ground, and any of the three in front of us
    isn't.
-   NOT A J
-   NOT B T
-   OR T J
-   NOT C T
-   OR T J
-   AND D J
+
+    !(A & B & C) & D
  */
 fn part1(input: &str) -> IntcodeResult<isize> {
-    let program = "NOT A J\nNOT B T\nOR T J\nNOT C T\nOR T J\nAND D J\nWALK\n";
-    let input_queue: VecDeque<char> = program.chars().collect();
-    let output_queue: VecDeque<isize> = VecDeque::new();
-    let io = Bus { input: input_queue, output: output_queue };
-    let cpu = CPU::parse(input)?;
-    let mut system = cpu.wrap(io);
-
-    system.run()?;
-
-    let last = *system.outer.output.back().ok_or(ExpectedOutput)?;
-
-    if last <= 255 {
-        let msg = system.outer.output.iter()
-            .map(|&i| (i as u8) as char)
-            .collect::<String>();
-
-        Err(LogicError(msg))
-    }
-    else {
-        Ok(last)
-    }
+    let program = compile_str("!(A & B & C) & D", Mode::Walk)?;
+    run(input, &program)
 }
 
 /*
@@ -44,51 +29,16 @@ fn part1(input: &str) -> IntcodeResult<isize> {
    @
    ##..#.##.
 
-   So, jump as before, UNLESS E is false and H is false.
-
-   (E | H) & D & (~A | ~B | ~C)
+   So, jump as before, unless E and H are both holes.  If E is ground we needn't jump yet anyway,
+   unless A is a hole, so only H matters as long as we always jump when A is a hole:
 
-   But this is hard to encode because we have two compounds.  What is the involvement of E?
+    (H & D & (!B | !C)) | !A
 
-   - If E is true, then we don't need to jump right now unless A is false.
-   - If E is false and H is false, don't jump now because we'll be stuck.
-
-   So, we can eliminate and just use H, but need to edit hte condition to always jump if the next
-   space is empty.
-
-    (H & D & (~B | ~C)) | ~A
-
-   NOT B J
-   NOT C T
-   OR T J
-   AND D J
-   AND H J
-   NOT A T
-   OR T J
+   `Synthesizer::synthesize` finds a policy like this from the droid's own reports of where it fell.
  */
 fn part2(input: &str) -> IntcodeResult<isize> {
-    let program = "NOT B J\nNOT C T\nOR T J\nAND D J\nAND H J\nNOT A T\nOR T J\nRUN\n";
-    let input_queue: VecDeque<char> = program.chars().collect();
-    let output_queue: VecDeque<isize> = VecDeque::new();
-    let io = Bus { input: input_queue, output: output_queue };
-    let cpu = CPU::parse(input)?;
-    let mut system = cpu.wrap(io);
-
-    system.run()?;
-
-    let last = *system.outer.output.back().ok_or(ExpectedOutput)?;
-
-    if last <= 255 {
-        let msg = system.outer.output.iter()
-            .map(|&i| (i as u8) as char)
-            .collect::<String>();
-
-        println!("{msg}");
-        Err(LogicError(msg))
-    }
-    else {
-        Ok(last)
-    }
+    let program = compile_str("(H & D & (!B | !C)) | !A", Mode::Run)?;
+    run(input, &program)
 }
 
 build_main_res!("day21.txt", "Part 1" => part1, "Part 2" => part2);
\ No newline at end of file
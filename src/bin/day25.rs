@@ -1,88 +1,483 @@
 use adventofcode2019::build_main_res;
+use adventofcode2019::intcode::ascii::{Ascii, AsciiIO};
 use adventofcode2019::intcode::cpu::CPU;
-use adventofcode2019::intcode::io::{IProvider, OProvider};
-use adventofcode2019::intcode::IntcodeError::InputFailure;
-use adventofcode2019::intcode::IntcodeState::{AwaitingInput, Continue, OutputGenerated};
-use adventofcode2019::intcode::{IntcodeResult, IntcodeState, Runnable};
-use std::collections::VecDeque;
-use std::io::stdin;
-
-struct Chunker {
-    input: VecDeque<char>,
-    output: VecDeque<char>
+use adventofcode2019::intcode::repl::{read_transcript, Repl};
+use adventofcode2019::intcode::IntcodeError::LogicError;
+use adventofcode2019::intcode::IntcodeState::{AwaitingInput, Halted, OutputGenerated};
+use adventofcode2019::intcode::{IntcodeResult, Runnable};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+
+// How long the droid may take to answer a command before we decide it never will.
+const PATIENCE: usize = 1_000_000;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+struct Room {
+    name: String,
+    doors: Vec<String>,
+    items: Vec<String>
 }
 
-impl IProvider for Chunker {
-    type PInput = isize;
-    type RInput = String;
+fn parse_rooms(lines: &[String]) -> Vec<Room> {
+    let mut rooms: Vec<Room> = Vec::new();
+    let mut listing_items = false;
 
-    fn provide_input<O>(&mut self) -> IntcodeResult<(IntcodeState<O>, Option<isize>)> {
-        let result = self.input.pop_front().map(|c| c as isize);
-        let st = if result.is_some() { Continue } else { AwaitingInput };
-        Ok((st, result))
+    for line in lines {
+        if let Some(name) = line.strip_prefix("== ").and_then(|l| l.strip_suffix(" ==")) {
+            rooms.push(Room { name: name.to_string(), ..Room::default() });
+        }
+        else if line == "Doors here lead:" {
+            listing_items = false;
+        }
+        else if line == "Items here:" {
+            listing_items = true;
+        }
+        else if let (Some(room), Some(entry)) = (rooms.last_mut(), line.strip_prefix("- ")) {
+            match listing_items {
+                true => room.items.push(entry.to_string()),
+                false => room.doors.push(entry.to_string())
+            }
+        }
     }
 
-    fn receive_input(&mut self, input: String) -> IntcodeResult<()> {
-        self.input.extend(input.chars());
-        Ok(())
+    rooms
+}
+
+fn password(lines: &[String]) -> Option<String> {
+    lines.iter()
+        .filter(|line| line.contains("airlock"))
+        .flat_map(|line| line.split_whitespace())
+        .find(|word| word.chars().all(|c| c.is_ascii_digit()))
+        .map(|word| word.to_string())
+}
+
+fn opposite(door: &str) -> IntcodeResult<&'static str> {
+    match door {
+        "north" => Ok("south"),
+        "south" => Ok("north"),
+        "east" => Ok("west"),
+        "west" => Ok("east"),
+        _ => Err(LogicError(format!("Unknown door {door}")))
+    }
+}
+
+struct Reply {
+    lines: Vec<String>,
+    halted: bool
+}
+
+// Runs the droid until it asks for a command or halts, or gives up on it and returns None.
+fn reply<M: Runnable<Output=Ascii>>(system: &mut M) -> IntcodeResult<Option<Reply>> {
+    let mut lines = Vec::new();
+
+    for _ in 0..PATIENCE {
+        match system.step()? {
+            OutputGenerated(Ascii::Line(line)) => lines.push(line),
+            OutputGenerated(Ascii::Value(value)) => lines.push(value.to_string()),
+            AwaitingInput => return Ok(Some(Reply { lines, halted: false })),
+            Halted => return Ok(Some(Reply { lines, halted: true })),
+            _ => ()
+        }
     }
+
+    Ok(None)
 }
 
-impl OProvider for Chunker {
-    type POutput = String;
-    type ROutput = isize;
+fn send<M: Runnable<Input=String, Output=Ascii>>(system: &mut M, command: &str) -> IntcodeResult<Option<Reply>> {
+    system.accept_input(format!("{command}\n"))?;
+    reply(system)
+}
+
+/*
+   Explores the ship depth first, picking up everything that's safe to carry, then walks to the
+   security checkpoint and tries sets of items on the pressure-sensitive floor.  A set the floor
+   finds too light rules out all of its subsets, and one it finds too heavy all of its supersets.
+ */
+struct Droid<M> {
+    system: M,
+    room: Room,
+    map: HashMap<String, HashMap<String, String>>,
+    floor: Option<(String, String)>,
+    items: Vec<String>,
+    password: Option<String>
+}
+
+impl<M: Runnable<Input=String, Output=Ascii> + Clone> Droid<M> {
+    fn new(mut system: M) -> IntcodeResult<Droid<M>> {
+        let reply = reply(&mut system)?.ok_or(LogicError("The droid never asked for a command".to_string()))?;
+        let room = parse_rooms(&reply.lines).pop().ok_or(LogicError("The droid isn't in a room".to_string()))?;
+
+        let map = HashMap::from([(room.name.clone(), HashMap::new())]);
+        Ok(Droid { system, room, map, floor: None, items: Vec::new(), password: None })
+    }
+
+    fn command(&mut self, command: &str) -> IntcodeResult<Reply> {
+        send(&mut self.system, command)?.ok_or(LogicError(format!("The droid got stuck after {command:?}")))
+    }
+
+    fn go(&mut self, door: &str) -> IntcodeResult<()> {
+        let reply = self.command(door)?;
+        if reply.halted {
+            self.password = password(&reply.lines);
+            return self.password.as_ref().map(|_| ()).ok_or(LogicError(reply.lines.join("\n")));
+        }
 
-    fn handle_output(&mut self, output: isize) -> IntcodeResult<IntcodeState<String>> {
-        let c = output as u8 as char;
-        self.output.push_back(c);
+        let rooms = parse_rooms(&reply.lines);
+        let room = rooms.last().cloned().ok_or(LogicError(format!("Couldn't go {door}")))?;
 
-        if c == '\n' {
-            let s = String::from_iter(self.output.iter().copied());
-            self.output.clear();
-            Ok(OutputGenerated(s))
+        // The floor weighs us and pushes us straight back out.
+        if rooms.len() > 1 {
+            self.floor = Some((self.room.name.clone(), door.to_string()));
         }
         else {
-            Ok(Continue)
+            self.map.entry(self.room.name.clone()).or_default().insert(door.to_string(), room.name.clone());
+            self.map.entry(room.name.clone()).or_default().insert(opposite(door)?.to_string(), self.room.name.clone());
         }
+
+        self.room = room;
+        Ok(())
     }
-}
 
-struct Console { buffer: String }
+    // Takes the item and then tries to leave the room, on a copy of the droid.
+    fn is_safe(&self, item: &str) -> IntcodeResult<bool> {
+        let mut system = self.system.clone();
 
-impl IProvider for Console {
-    type PInput = String;
-    type RInput = ();
+        match send(&mut system, &format!("take {item}"))? {
+            Some(Reply { halted: false, .. }) => (),
+            _ => return Ok(false)
+        }
 
-    fn provide_input<O>(&mut self) -> IntcodeResult<(IntcodeState<O>, Option<Self::PInput>)> {
-        let mut s = String::new();
-        stdin().read_line(&mut s).map_err(|_| InputFailure)?;
-        Ok((Continue, Some(s)))
+        let door = self.room.doors.first().ok_or(LogicError(format!("{} has no doors", self.room.name)))?;
+        match send(&mut system, door)? {
+            Some(Reply { lines, halted: false }) => Ok(!parse_rooms(&lines).is_empty()),
+            Some(Reply { lines, halted: true }) => Ok(password(&lines).is_some()),
+            None => Ok(false)
+        }
     }
 
-    fn receive_input(&mut self, _: Self::RInput) -> IntcodeResult<()> {
-        Err(InputFailure)
+    fn collect(&mut self) -> IntcodeResult<()> {
+        for item in self.room.items.clone() {
+            if self.is_safe(&item)? {
+                self.command(&format!("take {item}"))?;
+                self.items.push(item);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Visits every room reachable from this one that we haven't seen yet, then comes back.
+    fn explore(&mut self) -> IntcodeResult<()> {
+        self.collect()?;
+        let here = self.room.name.clone();
+
+        for door in self.room.doors.clone() {
+            if self.password.is_some() || self.map[&here].contains_key(&door) {
+                continue;
+            }
+
+            let seen = self.map.len();
+            self.go(&door)?;
+            if self.room.name == here {
+                continue;
+            }
+
+            if self.map.len() > seen {
+                self.explore()?;
+            }
+            self.go(opposite(&door)?)?;
+        }
+
+        Ok(())
+    }
+
+    fn path(&self, to: &str) -> IntcodeResult<Vec<String>> {
+        let mut queue = VecDeque::from([(self.room.name.clone(), Vec::new())]);
+        let mut seen = vec![self.room.name.clone()];
+
+        while let Some((room, doors)) = queue.pop_front() {
+            if room == to {
+                return Ok(doors);
+            }
+
+            for (door, next) in self.map[&room].iter() {
+                if !seen.contains(next) {
+                    seen.push(next.clone());
+                    let mut doors = doors.clone();
+                    doors.push(door.clone());
+                    queue.push_back((next.clone(), doors));
+                }
+            }
+        }
+
+        Err(LogicError(format!("No way to get to {to}")))
     }
-}
 
-impl OProvider for Console {
-    type POutput = ();
-    type ROutput = String;
+    fn weigh_in(&mut self) -> IntcodeResult<String> {
+        let (checkpoint, floor) = self.floor.clone()
+            .ok_or(LogicError("Never found the pressure-sensitive floor".to_string()))?;
 
-    fn handle_output(&mut self, output: String) -> IntcodeResult<IntcodeState<()>> {
-        print!("{output}");
-        Ok(Continue)
+        for door in self.path(&checkpoint)? {
+            self.go(&door)?;
+        }
+
+        let items = self.items.clone();
+        let mut carried: u32 = (1 << items.len()) - 1;
+        let mut too_light: Vec<u32> = Vec::new();
+        let mut too_heavy: Vec<u32> = Vec::new();
+
+        for mask in 0..(1 << items.len()) {
+            if too_light.iter().any(|&l| mask & !l == 0) || too_heavy.iter().any(|&h| h & !mask == 0) {
+                continue;
+            }
+
+            for (i, item) in items.iter().enumerate() {
+                match (carried & (1 << i) != 0, mask & (1 << i) != 0) {
+                    (true, false) => self.command(&format!("drop {item}"))?,
+                    (false, true) => self.command(&format!("take {item}"))?,
+                    _ => continue
+                };
+            }
+            carried = mask;
+
+            let reply = self.command(&floor)?;
+            if reply.halted {
+                return password(&reply.lines).ok_or(LogicError(reply.lines.join("\n")));
+            }
+
+            match reply.lines.iter().find(|line| line.contains("than the detected value")) {
+                Some(alert) if alert.contains("heavier") => too_light.push(mask),
+                Some(_) => too_heavy.push(mask),
+                None => return Err(LogicError(reply.lines.join("\n")))
+            }
+        }
+
+        Err(LogicError("No set of items got past the checkpoint".to_string()))
+    }
+
+    fn solve(mut self) -> IntcodeResult<String> {
+        self.explore()?;
+
+        match self.password.take() {
+            Some(password) => Ok(password),
+            None => self.weigh_in()
+        }
     }
 }
 
-fn part1(input: &str) -> IntcodeResult<String> {
-    let cpu = CPU::parse(input)?;
-    let chunker = Chunker { input: VecDeque::new(), output: VecDeque::new() };
-    let console = Console { buffer: String::new() };
+/*
+   Plays the adventure in the terminal instead, when given any of
 
-    let mut system = cpu.wrap(chunker).wrap(console);
-    system.run()?;
+   --record <file>   write every command to <file>
+   --replay <file>   send the commands in <file> first
+   --stop <n>        only replay the first n of them, then carry on by hand
+ */
+fn play(input: &str) -> IntcodeResult<String> {
+    let mut repl = Repl::new(CPU::parse(input)?.wrap(AsciiIO::new()));
+    let mut replay = None;
+    let mut stop = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(LogicError(format!("Expected a value after {flag}")))?;
+        match flag.as_str() {
+            "--record" => repl = repl.with_recording(value)?,
+            "--replay" => replay = Some(read_transcript(value)?),
+            "--stop" => stop = Some(value.parse::<usize>().map_err(|e| LogicError(e.to_string()))?),
+            _ => return Err(LogicError(format!("Unknown option {flag}")))
+        }
+    }
+
+    if let Some(mut commands) = replay {
+        commands.truncate(stop.unwrap_or(commands.len()));
+        repl = repl.with_replay(commands);
+    }
+
+    repl.interact()?;
 
     Ok("Done".to_string())
 }
 
-build_main_res!("day25.txt", "Part 1" => part1);
\ No newline at end of file
+fn part1(input: &str) -> IntcodeResult<String> {
+    if env::args().len() > 1 {
+        return play(input);
+    }
+
+    Droid::new(CPU::parse(input)?.wrap(AsciiIO::new()))?.solve()
+}
+
+build_main_res!("day25.txt", "Part 1" => part1);
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use adventofcode2019::intcode::IntcodeState;
+    use adventofcode2019::intcode::IntcodeState::Continue;
+
+    const FLOOR: usize = usize::MAX;
+
+    // A small ship in the same words as the real one, with a floor that wants a coin and a book.
+    const ROOMS: [(&str, &[(&str, usize)]); 4] = [
+        ("Hull Breach", &[("north", 1), ("east", 2)]),
+        ("Kitchen", &[("south", 0)]),
+        ("Hallway", &[("west", 0), ("north", 3)]),
+        ("Security Checkpoint", &[("south", 2), ("north", FLOOR)])
+    ];
+
+    fn weight(item: &str) -> usize {
+        match item {
+            "mug" => 1,
+            "coin" => 2,
+            "book" => 4,
+            _ => 100
+        }
+    }
+
+    #[derive(Clone)]
+    struct Ship {
+        room: usize,
+        items: Vec<Vec<&'static str>>,
+        carrying: Vec<&'static str>,
+        input: String,
+        output: VecDeque<Ascii>,
+        halted: bool,
+        looping: bool
+    }
+
+    impl Ship {
+        fn new() -> Ship {
+            let items = vec![
+                vec!["mug"],
+                vec!["coin", "molten lava", "infinite loop"],
+                vec!["giant electromagnet", "book"],
+                vec![]
+            ];
+            let mut ship = Ship {
+                room: 0, items, carrying: vec![], input: String::new(), output: VecDeque::new(),
+                halted: false, looping: false
+            };
+            ship.describe(0);
+            ship.say("Command?");
+            ship
+        }
+
+        fn say(&mut self, line: &str) {
+            self.output.push_back(Ascii::Line(line.to_string()));
+        }
+
+        fn describe(&mut self, room: usize) {
+            let (name, doors) = ROOMS[room];
+            self.say("");
+            self.say(&format!("== {name} =="));
+            self.say("");
+            self.say("Doors here lead:");
+            for (door, _) in doors {
+                self.say(&format!("- {door}"));
+            }
+            if !self.items[room].is_empty() {
+                self.say("");
+                self.say("Items here:");
+                for item in self.items[room].clone() {
+                    self.say(&format!("- {item}"));
+                }
+            }
+            self.say("");
+        }
+
+        fn execute(&mut self, command: &str) {
+            if let Some(item) = command.strip_prefix("take ") {
+                let Some(i) = self.items[self.room].iter().position(|&it| it == item) else { return };
+                match item {
+                    "molten lava" => {
+                        self.say("You melt!");
+                        self.halted = true;
+                    },
+                    "infinite loop" => self.looping = true,
+                    _ => {
+                        let item = self.items[self.room].remove(i);
+                        self.carrying.push(item);
+                        self.say(&format!("You take the {item}."));
+                    }
+                }
+            }
+            else if let Some(item) = command.strip_prefix("drop ") {
+                let Some(i) = self.carrying.iter().position(|&it| it == item) else { return };
+                let item = self.carrying.remove(i);
+                self.items[self.room].push(item);
+                self.say(&format!("You drop the {item}."));
+            }
+            else if self.carrying.contains(&"giant electromagnet") {
+                self.say("The giant electromagnet is stuck to you.  You can't move!!");
+            }
+            else if let Some(&(_, dest)) = ROOMS[self.room].1.iter().find(|&&(door, _)| door == command) {
+                if dest == FLOOR {
+                    self.say("== Pressure-Sensitive Floor ==");
+                    match self.carrying.iter().map(|item| weight(item)).sum::<usize>() {
+                        6 => {
+                            self.say("Oh, hello! You should be able to get in by typing 1234 on the keypad at the main airlock.");
+                            self.halted = true;
+                        },
+                        w if w < 6 => self.say("Alert! Droids on this ship are heavier than the detected value!"),
+                        _ => self.say("Alert! Droids on this ship are lighter than the detected value!")
+                    }
+                    if self.halted {
+                        return;
+                    }
+                    self.describe(self.room);
+                }
+                else {
+                    self.room = dest;
+                    self.describe(dest);
+                }
+            }
+            else {
+                self.say("You can't go that way.");
+            }
+
+            if !self.halted {
+                self.say("Command?");
+            }
+        }
+    }
+
+    impl Runnable for Ship {
+        type Input = String;
+        type Output = Ascii;
+
+        fn accept_input(&mut self, input: String) -> IntcodeResult<()> {
+            self.input.push_str(&input);
+            Ok(())
+        }
+
+        fn step(&mut self) -> IntcodeResult<IntcodeState<Ascii>> {
+            if self.looping {
+                return Ok(Continue);
+            }
+            if let Some(output) = self.output.pop_front() {
+                return Ok(OutputGenerated(output));
+            }
+            if self.halted {
+                return Ok(Halted);
+            }
+
+            match self.input.find('\n') {
+                Some(i) => {
+                    let command: String = self.input.drain(..=i).collect();
+                    self.execute(command.trim_end());
+                    Ok(Continue)
+                },
+                None => Ok(AwaitingInput)
+            }
+        }
+    }
+
+    #[test]
+    fn test_droid() {
+        let mut droid = Droid::new(Ship::new()).unwrap();
+        droid.explore().unwrap();
+        assert_eq!(droid.items, ["mug", "coin", "book"]);
+        assert_eq!(droid.floor, Some(("Security Checkpoint".to_string(), "north".to_string())));
+        assert_eq!(droid.room.name, "Hull Breach");
+        assert_eq!(droid.weigh_in(), Ok("1234".to_string()));
+    }
+}
@@ -1,8 +1,27 @@
 use crate::intcode::IntcodeState::*;
-use crate::intcode::io::{IProvider, OProvider};
+use crate::intcode::io::{Bus, IProvider, OProvider};
+use std::fmt::{Display, Formatter};
 
 pub mod io;
 pub mod cpu;
+pub mod memory;
+pub mod word;
+pub mod disasm;
+pub mod asm;
+pub mod debugger;
+pub mod trace;
+pub mod snapshot;
+pub mod transpile;
+pub mod profile;
+pub mod cfg;
+pub mod calls;
+pub mod network;
+pub mod threaded;
+pub mod pipeline;
+pub mod ascii;
+pub mod repl;
+pub mod springscript;
+pub mod springdroid;
 
 
 #[derive(Debug, Eq, PartialEq)]
@@ -10,18 +29,80 @@ pub enum IntcodeError {
     BadParameterMode(isize),
     BadOpCode(isize),
     WriteToImmediate,
+    NegativeAddress(isize),
+    BadAddress(String),
+    Overflow(isize),
+    MemoryLimit(usize),
+    OutputLimit(usize),
     ParsingFailure(String),
+    AssemblyFailure(usize, String),
+    SnapshotFailure(String),
     LogicError(String),
     ExpectedOutput,
-    InputFailure
+    InputFailure,
+    InMachine(Box<IntcodeError>, MachineContext)
 }
 
+// Where a CPU was when it hit an error; `instr` is the raw word at the instruction pointer.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MachineContext {
+    pub instr_ptr: isize,
+    pub rel_base: isize,
+    pub instr: String,
+    pub steps: usize
+}
+
+impl IntcodeError {
+    // The error itself, without the machine context around it.
+    pub fn cause(&self) -> &IntcodeError {
+        match self {
+            IntcodeError::InMachine(e, _) => e.cause(),
+            e => e
+        }
+    }
+
+    pub fn context(&self) -> Option<&MachineContext> {
+        match self {
+            IntcodeError::InMachine(_, context) => Some(context),
+            _ => None
+        }
+    }
+}
+
+impl Display for IntcodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntcodeError::BadParameterMode(mode) => write!(f, "bad parameter mode {mode}"),
+            IntcodeError::BadOpCode(op) => write!(f, "bad opcode {op}"),
+            IntcodeError::WriteToImmediate => write!(f, "write to an immediate parameter"),
+            IntcodeError::NegativeAddress(addr) => write!(f, "negative address {addr}"),
+            IntcodeError::BadAddress(addr) => write!(f, "bad address {addr}"),
+            IntcodeError::Overflow(addr) => write!(f, "arithmetic overflow at {addr}"),
+            IntcodeError::MemoryLimit(max) => write!(f, "memory limit of {max} words exceeded"),
+            IntcodeError::OutputLimit(max) => write!(f, "output limit of {max} exceeded"),
+            IntcodeError::ParsingFailure(msg) => write!(f, "parsing failed: {msg}"),
+            IntcodeError::AssemblyFailure(line, msg) => write!(f, "assembly failed on line {line}: {msg}"),
+            IntcodeError::SnapshotFailure(msg) => write!(f, "bad snapshot: {msg}"),
+            IntcodeError::LogicError(msg) => write!(f, "{msg}"),
+            IntcodeError::ExpectedOutput => write!(f, "expected an output"),
+            IntcodeError::InputFailure => write!(f, "couldn't take input"),
+            IntcodeError::InMachine(e, context) => {
+                let MachineContext { instr_ptr, rel_base, instr, steps } = context;
+                write!(f, "{e} (at {instr_ptr}: {instr}, relative base {rel_base}, after {steps} steps)")
+            }
+        }
+    }
+}
+
+impl std::error::Error for IntcodeError {}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum IntcodeState<T> {
     Continue,
     OutputGenerated(T),
     Halted,
-    AwaitingInput
+    AwaitingInput,
+    OutOfSteps
 }
 
 pub type IntcodeResult<T> = Result<T, IntcodeError>;
@@ -39,7 +120,7 @@ pub trait Runnable: Sized {
             let state = self.step()?;
 
             match &state {
-                Halted | AwaitingInput => return Ok(state),
+                Halted | AwaitingInput | OutOfSteps => return Ok(state),
                 _ => continue
             }
         }
@@ -49,7 +130,7 @@ pub trait Runnable: Sized {
         loop {
             match self.step()? {
                 OutputGenerated(o) => return Ok(o),
-                AwaitingInput | IntcodeState::Halted => return Err(IntcodeError::ExpectedOutput),
+                AwaitingInput | IntcodeState::Halted | OutOfSteps => return Err(IntcodeError::ExpectedOutput),
                 _ => ()
             }
         }
@@ -59,12 +140,19 @@ pub trait Runnable: Sized {
     where IO: IProvider<PInput=Self::Input> + OProvider<ROutput=Self::Output> {
         IOWrapper { outer: io, inner: self }
     }
+
+    // Wraps the machine with separate input and output providers.
+    fn connect<I, O>(self, input: I, output: O) -> IOWrapper<Bus<I, O>, Self>
+    where I: IProvider<PInput=Self::Input>, O: OProvider<ROutput=Self::Output> {
+        self.wrap(Bus { input, output })
+    }
 }
 
 pub trait Resettable {
     fn reset(&mut self);
 }
 
+#[derive(Clone)]
 pub struct IOWrapper<Outer, Inner> {
     pub outer: Outer,
     pub inner: Inner
@@ -92,6 +180,7 @@ where Outer: IProvider + OProvider, Inner: Runnable<Input=Outer::PInput, Output=
                 Ok(new_state)
             },
             Halted => Ok(Halted),
+            OutOfSteps => Ok(OutOfSteps),
             Continue => Ok(Continue)
         }
     }
@@ -0,0 +1,125 @@
+use crate::intcode::IntcodeError::LogicError;
+use crate::intcode::IntcodeState::{AwaitingInput, Continue, OutputGenerated};
+use crate::intcode::io::{IProvider, OProvider};
+use crate::intcode::{IntcodeResult, IntcodeState, Resettable};
+use itertools::Itertools;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Ascii {
+    Line(String),
+    Value(isize)
+}
+
+/*
+   Talks to a program in ASCII.  Commands are queued as text and fed to the program a character at
+   a time; output is collected into lines, produced without their newline once each one ends.  Any
+   output outside the ASCII range, like a final answer, is produced as a value instead.  Everything
+   produced is also kept in `lines` and `values`.
+ */
+#[derive(Clone, Default)]
+pub struct AsciiIO {
+    input: VecDeque<isize>,
+    line: String,
+    pub lines: Vec<String>,
+    pub values: Vec<isize>
+}
+
+impl AsciiIO {
+    pub fn new() -> AsciiIO {
+        AsciiIO::default()
+    }
+
+    pub fn with_input(mut self, command: &str) -> IntcodeResult<AsciiIO> {
+        self.send(command)?;
+        Ok(self)
+    }
+
+    // Queues the text as it is; commands usually need to end with a newline.
+    pub fn send(&mut self, command: &str) -> IntcodeResult<()> {
+        if let Some(c) = command.chars().find(|c| !c.is_ascii()) {
+            return Err(LogicError(format!("Can't send {c:?} to an ASCII program")));
+        }
+
+        self.input.extend(command.bytes().map(|b| b as isize));
+        Ok(())
+    }
+
+    // Everything printed so far, including any unfinished last line.
+    pub fn text(&self) -> String {
+        self.lines.iter().map(|line| format!("{line}\n")).join("") + &self.line
+    }
+}
+
+impl IProvider for AsciiIO {
+    type PInput = isize;
+    type RInput = String;
+
+    fn provide_input<O>(&mut self) -> IntcodeResult<(IntcodeState<O>, Option<isize>)> {
+        match self.input.pop_front() {
+            Some(c) => Ok((Continue, Some(c))),
+            None => Ok((AwaitingInput, None))
+        }
+    }
+
+    fn receive_input(&mut self, command: String) -> IntcodeResult<()> {
+        self.send(&command)
+    }
+}
+
+impl OProvider for AsciiIO {
+    type POutput = Ascii;
+    type ROutput = isize;
+
+    fn handle_output(&mut self, output: isize) -> IntcodeResult<IntcodeState<Ascii>> {
+        match u8::try_from(output).ok().filter(u8::is_ascii) {
+            Some(b'\n') => {
+                let line = std::mem::take(&mut self.line);
+                self.lines.push(line.clone());
+                Ok(OutputGenerated(Ascii::Line(line)))
+            },
+            Some(b) => {
+                self.line.push(b as char);
+                Ok(Continue)
+            },
+            None => {
+                self.values.push(output);
+                Ok(OutputGenerated(Ascii::Value(output)))
+            }
+        }
+    }
+}
+
+impl Resettable for AsciiIO {
+    fn reset(&mut self) {
+        self.input.clear();
+        self.line.clear();
+        self.lines.clear();
+        self.values.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::cpu::CPU;
+    use crate::intcode::IntcodeState::Halted;
+    use crate::intcode::Runnable;
+
+    #[test]
+    fn test_ascii() {
+        // Echoes its input forever.
+        let echo = CPU::parse("3,7,4,7,1105,1,0,0").unwrap();
+        let mut system = echo.wrap(AsciiIO::new().with_input("hi\nthere").unwrap());
+        assert_eq!(system.run_until_output(), Ok(Ascii::Line("hi".to_string())));
+        assert_eq!(system.run(), Ok(AwaitingInput));
+        assert_eq!(system.outer.text(), "hi\nthere");
+        assert!(system.accept_input("café\n".to_string()).is_err());
+
+        // Prints "Hi", then 1000 and 200, neither of which is ASCII.
+        let mut system = CPU::parse("104,72,104,105,104,10,104,1000,104,200,99").unwrap().wrap(AsciiIO::new());
+        assert_eq!(system.run(), Ok(Halted));
+        assert_eq!(system.outer.lines, ["Hi"]);
+        assert_eq!(system.outer.values, [1000, 200]);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::intcode::IntcodeError::AssemblyFailure;
+use crate::intcode::IntcodeResult;
+use crate::parsers::isize_str;
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{alpha1, alphanumeric1, char, space0, space1};
+use nom::combinator::{all_consuming, map, opt, recognize};
+use nom::multi::{many0_count, separated_list1};
+use nom::sequence::{delimited, pair, preceded, terminated, tuple};
+use nom::IResult;
+use std::collections::HashMap;
+
+/*
+   Source is one statement per line:
+
+       label:  MNEMONIC op, op, op     ; comment
+               .data 1, -2, label+3
+
+   Operands are `[x]` (position), `#x` (immediate) or `rb+n` / `rb-n` (relative), where x is a
+   number, a label, or a label with a `+n` / `-n` offset.  Labels may also sit on a line by
+   themselves, and mnemonics are case-insensitive.
+ */
+
+const OPS: [(&str, isize, usize); 10] = [
+    ("ADD", 1, 3),
+    ("MUL", 2, 3),
+    ("IN", 3, 1),
+    ("OUT", 4, 1),
+    ("JT", 5, 2),
+    ("JF", 6, 2),
+    ("LT", 7, 3),
+    ("EQ", 8, 3),
+    ("ARB", 9, 1),
+    ("HLT", 99, 0)
+];
+
+#[derive(Debug, Clone)]
+enum Value<'a> {
+    Number(isize),
+    Label(&'a str, isize)
+}
+
+#[derive(Debug, Clone)]
+enum Operand<'a> {
+    Position(Value<'a>),
+    Immediate(Value<'a>),
+    Relative(isize)
+}
+
+#[derive(Debug)]
+enum Statement<'a> {
+    Instruction(&'a str, Vec<Operand<'a>>),
+    Data(Vec<Value<'a>>)
+}
+
+struct Line<'a> {
+    label: Option<&'a str>,
+    statement: Option<Statement<'a>>
+}
+
+fn comma(input: &str) -> IResult<&str, char> {
+    delimited(space0, char(','), space0)(input)
+}
+
+fn ident(input: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        alt((alpha1, tag("_"))),
+        many0_count(alt((alphanumeric1, tag("_"))))
+    ))(input)
+}
+
+fn offset(input: &str) -> IResult<&str, isize> {
+    alt((preceded(char('+'), isize_str), isize_str))(input)
+}
+
+fn value(input: &str) -> IResult<&str, Value<'_>> {
+    alt((
+        map(isize_str, Value::Number),
+        map(pair(ident, opt(offset)), |(label, off)| Value::Label(label, off.unwrap_or(0)))
+    ))(input)
+}
+
+fn operand(input: &str) -> IResult<&str, Operand<'_>> {
+    alt((
+        map(delimited(char('['), delimited(space0, value, space0), char(']')), Operand::Position),
+        map(preceded(char('#'), value), Operand::Immediate),
+        map(preceded(tag("rb"), opt(offset)), |off| Operand::Relative(off.unwrap_or(0)))
+    ))(input)
+}
+
+fn statement(input: &str) -> IResult<&str, Statement<'_>> {
+    alt((
+        map(preceded(pair(tag(".data"), space1), separated_list1(comma, value)), Statement::Data),
+        map(
+            pair(ident, opt(preceded(space1, separated_list1(comma, operand)))),
+            |(mnemonic, operands)| Statement::Instruction(mnemonic, operands.unwrap_or_default())
+        )
+    ))(input)
+}
+
+fn parse_line(input: &str) -> IResult<&str, Line<'_>> {
+    map(
+        all_consuming(delimited(
+            space0,
+            tuple((opt(terminated(ident, pair(char(':'), space0))), opt(statement))),
+            space0
+        )),
+        |(label, statement)| Line { label, statement }
+    )(input)
+}
+
+fn err<T>(line_no: usize, msg: String) -> IntcodeResult<T> {
+    Err(AssemblyFailure(line_no, msg))
+}
+
+fn resolve(line_no: usize, value: &Value, labels: &HashMap<&str, isize>) -> IntcodeResult<isize> {
+    match *value {
+        Value::Number(n) => Ok(n),
+        Value::Label(label, off) => match labels.get(label) {
+            Some(&addr) => Ok(addr + off),
+            None => err(line_no, format!("undefined label `{label}`"))
+        }
+    }
+}
+
+pub fn assemble(source: &str) -> IntcodeResult<Vec<isize>> {
+    let mut parsed = Vec::new();
+    let mut labels = HashMap::new();
+    let mut addr = 0;
+
+    for (i, text) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let text = text.split(';').next().unwrap_or_default();
+        let Ok((_, line)) = parse_line(text) else {
+            return err(line_no, format!("could not parse `{}`", text.trim()));
+        };
+
+        if let Some(label) = line.label {
+            if label == "rb" {
+                return err(line_no, "`rb` is reserved and cannot be used as a label".to_string());
+            }
+            if labels.insert(label, addr).is_some() {
+                return err(line_no, format!("label `{label}` is defined more than once"));
+            }
+        }
+
+        match &line.statement {
+            Some(Statement::Instruction(mnemonic, operands)) => {
+                let upper = mnemonic.to_ascii_uppercase();
+                let Some(&(_, _, arity)) = OPS.iter().find(|(m, _, _)| *m == upper) else {
+                    return err(line_no, format!("unknown mnemonic `{mnemonic}`"));
+                };
+
+                if operands.len() != arity {
+                    let msg = format!("{upper} takes {arity} operand(s), got {}", operands.len());
+                    return err(line_no, msg);
+                }
+
+                addr += 1 + arity as isize;
+            },
+            Some(Statement::Data(values)) => addr += values.len() as isize,
+            None => ()
+        }
+
+        parsed.push((line_no, line));
+    }
+
+    let mut program = Vec::new();
+
+    for (line_no, line) in parsed {
+        match line.statement {
+            Some(Statement::Instruction(mnemonic, operands)) => {
+                let upper = mnemonic.to_ascii_uppercase();
+                let &(_, op_code, _) = OPS.iter().find(|(m, _, _)| *m == upper).unwrap();
+
+                let writes = match op_code {
+                    1 | 2 | 7 | 8 => Some(2),
+                    3 => Some(0),
+                    _ => None
+                };
+
+                let mut instr = op_code;
+                let mut params = Vec::with_capacity(operands.len());
+
+                for (j, operand) in operands.iter().enumerate() {
+                    let (mode, param) = match operand {
+                        Operand::Position(v) => (0, resolve(line_no, v, &labels)?),
+                        Operand::Immediate(_) if writes == Some(j) => {
+                            let msg = format!("operand {} of {upper} is written to and cannot be immediate", j + 1);
+                            return err(line_no, msg);
+                        },
+                        Operand::Immediate(v) => (1, resolve(line_no, v, &labels)?),
+                        Operand::Relative(off) => (2, *off)
+                    };
+
+                    instr += mode * 10isize.pow(j as u32 + 2);
+                    params.push(param);
+                }
+
+                program.push(instr);
+                program.extend(params);
+            },
+            Some(Statement::Data(values)) => {
+                for v in values.iter() {
+                    program.push(resolve(line_no, v, &labels)?);
+                }
+            },
+            None => ()
+        }
+    }
+
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::cpu::CPU;
+    use crate::intcode::disasm::{disassemble, Line};
+    use crate::intcode::io::IOQueues;
+    use crate::intcode::Runnable;
+
+    #[test]
+    fn test_countdown() {
+        let source = "
+            ; count down from the input value, printing each number
+                    IN [n]
+            loop:   OUT [n]
+                    ADD [n], #-1, [n]
+                    JT [n], #loop
+                    HLT
+            n:      .data 0
+        ";
+
+        let program = assemble(source).unwrap();
+        assert_eq!(program, vec![3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0]);
+
+        let mut system = CPU::new(program).wrap(IOQueues::new());
+        system.accept_input(3).unwrap();
+        system.run().unwrap();
+        assert_eq!(system.outer.output, [3, 2, 1]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let code = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let program = crate::intcode::cpu::parse_code(code).unwrap();
+
+        for line in disassemble(&program).lines {
+            if let Line::Code { raw, instr, .. } = line {
+                assert_eq!(assemble(&instr.to_string()).unwrap(), raw);
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(assemble("HLT\nFOO #1"), Err(AssemblyFailure(2, "unknown mnemonic `FOO`".to_string())));
+        assert_eq!(assemble("JT #1, #nowhere"), Err(AssemblyFailure(1, "undefined label `nowhere`".to_string())));
+        assert!(matches!(assemble("\n\nADD #1, #2, #3"), Err(AssemblyFailure(3, _))));
+        assert!(matches!(assemble("a: HLT\na: HLT"), Err(AssemblyFailure(2, _))));
+    }
+}
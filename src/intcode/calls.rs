@@ -0,0 +1,196 @@
+use crate::intcode::cpu::Instruction::{JumpIfFalse, JumpIfTrue};
+use crate::intcode::cpu::{Parameter, CPU};
+use crate::intcode::memory::{Paged, Storage};
+use crate::intcode::word::Word;
+use crate::intcode::{IntcodeResult, IntcodeState, Resettable, Runnable};
+use itertools::Itertools;
+
+/*
+   Puzzle programs call a function by storing the return address relative to the relative base
+   and then jumping to the function; the function returns with an indirect jump through that
+   stored address.  So a taken jump counts as a call if, since the last jump, the address just
+   after it was written through a relative-mode parameter, and as a return if it is indirect and
+   lands on the return address of a frame on the stack (frames above it are dropped as well).
+ */
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Frame {
+    pub entry: isize,
+    pub call_site: isize,
+    pub ret: isize,
+    pub rel_base: isize
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct CallStack {
+    pub frames: Vec<Frame>,
+    pushed: Vec<isize>
+}
+
+// What an instruction was about to do, taken just before it runs.
+#[derive(Debug, Copy, Clone)]
+pub struct Pending {
+    ip: isize,
+    next: isize,
+    jump: Option<bool>,
+    push: Option<isize>
+}
+
+impl CallStack {
+    pub fn new() -> CallStack {
+        CallStack::default()
+    }
+
+    pub fn before<W: Word, S: Storage<W>>(&self, cpu: &CPU<W, S>) -> Pending {
+        let ip = cpu.instr_ptr;
+        let instr = cpu.cur_instr().ok();
+
+        let jump = match &instr {
+            Some(JumpIfTrue(_, target) | JumpIfFalse(_, target)) => {
+                Some(!matches!(target, Parameter::Immediate(_)))
+            },
+            _ => None
+        };
+
+        let push = match instr.as_ref().and_then(|i| i.target()) {
+            Some(param @ Parameter::Relative(_)) => cpu.address(param),
+            _ => None
+        };
+
+        let next = ip + instr.map_or(1, |i| i.width());
+        Pending { ip, next, jump, push }
+    }
+
+    pub fn after<W: Word, S: Storage<W>>(&mut self, pending: Pending, cpu: &CPU<W, S>) {
+        // An instruction waiting for input hasn't run yet.
+        if cpu.instr_ptr == pending.ip && pending.jump.is_none() {
+            return;
+        }
+
+        if let Some(addr) = pending.push {
+            if let Some(value) = cpu.memory.get(addr).ok().and_then(|v| v.to_isize()) {
+                self.pushed.push(value);
+            }
+        }
+
+        let Some(indirect) = pending.jump else { return };
+        let target = cpu.instr_ptr;
+        if target == pending.next {
+            return;
+        }
+
+        let returning_to = self.frames.iter().rposition(|frame| frame.ret == target);
+        match returning_to {
+            Some(depth) if indirect => self.frames.truncate(depth),
+            _ if self.pushed.contains(&pending.next) => {
+                let (call_site, ret, rel_base) = (pending.ip, pending.next, cpu.rel_base);
+                self.frames.push(Frame { entry: target, call_site, ret, rel_base });
+            },
+            _ => ()
+        }
+
+        self.pushed.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.pushed.clear();
+    }
+
+    /*
+       Innermost frame first, as `#n  <address>  in <function entry>`.  The outermost frame is the
+       program itself, entered at 0.
+     */
+    pub fn backtrace(&self, ip: isize) -> String {
+        let addrs = std::iter::once(ip).chain(self.frames.iter().rev().map(|frame| frame.call_site));
+        let entries = self.frames.iter().rev().map(|frame| frame.entry).chain(std::iter::once(0));
+
+        addrs.zip(entries)
+            .enumerate()
+            .map(|(i, (addr, entry))| format!("#{i}  {addr:05}  in {entry:05}"))
+            .join("\n")
+    }
+}
+
+#[derive(Clone)]
+pub struct Backtraced<W: Word = isize, S: Storage<W> = Paged<W>> {
+    pub cpu: CPU<W, S>,
+    pub calls: CallStack
+}
+
+impl<W: Word, S: Storage<W>> Backtraced<W, S> {
+    pub fn new(cpu: CPU<W, S>) -> Backtraced<W, S> {
+        Backtraced { cpu, calls: CallStack::new() }
+    }
+
+    pub fn backtrace(&self) -> String {
+        self.calls.backtrace(self.cpu.instr_ptr)
+    }
+}
+
+impl<W: Word, S: Storage<W>> CPU<W, S> {
+    pub fn backtraced(self) -> Backtraced<W, S> {
+        Backtraced::new(self)
+    }
+}
+
+impl<W: Word, S: Storage<W>> Runnable for Backtraced<W, S> {
+    type Input = W;
+    type Output = W;
+
+    fn accept_input(&mut self, input: W) -> IntcodeResult<()> {
+        self.cpu.accept_input(input)
+    }
+
+    fn step(&mut self) -> IntcodeResult<IntcodeState<W>> {
+        let pending = self.calls.before(&self.cpu);
+        let state = self.cpu.step()?;
+        self.calls.after(pending, &self.cpu);
+        Ok(state)
+    }
+}
+
+impl<W: Word, S: Storage<W>> Resettable for Backtraced<W, S> {
+    fn reset(&mut self) {
+        self.cpu.reset();
+        self.calls.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::IntcodeError::BadOpCode;
+
+    #[test]
+    fn test_backtrace() {
+        // main calls outer twice; the second time outer calls inner, which runs into garbage.
+        let program = assemble("
+                ARB #100
+                ADD #back, #0, rb+0
+                JT #1, #outer
+        back:   ADD #1, #0, [flag]
+                ADD #done, #0, rb+0
+                JT #1, #outer
+        done:   HLT
+
+        outer:  ARB #2
+                JF [flag], #leave
+                ADD #leave, #0, rb+0
+                JT #1, #inner
+        leave:  ARB #-2
+                JT #1, rb+0
+
+        inner:  ARB #1
+                .data 42
+
+        flag:   .data 0
+        ").unwrap();
+
+        let mut traced = CPU::new(program).backtraced();
+        assert_eq!(traced.run().unwrap_err().cause(), &BadOpCode(42));
+        let frames = traced.calls.frames.iter().map(|f| (f.entry, f.call_site)).collect_vec();
+        assert_eq!(frames, vec![(21, 17), (38, 30)]);
+        assert_eq!(traced.backtrace(), "#0  00040  in 00038\n#1  00030  in 00021\n#2  00017  in 00000");
+    }
+}
@@ -0,0 +1,209 @@
+use crate::intcode::cpu::Instruction::{Done, JumpIfFalse, JumpIfTrue};
+use crate::intcode::cpu::{Instruction, Parameter};
+use crate::intcode::disasm::{disassemble, Line};
+use itertools::Itertools;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Edge {
+    Taken(isize),
+    Fallthrough(isize)
+}
+
+impl Edge {
+    pub fn target(&self) -> isize {
+        match self {
+            Edge::Taken(addr) | Edge::Fallthrough(addr) => *addr
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Block {
+    pub start: isize,
+    pub instrs: Vec<(isize, Instruction)>,
+    pub edges: Vec<Edge>,
+    // Ends in a jump whose target is only known at run time.
+    pub unresolved: bool
+}
+
+impl Block {
+    pub fn end(&self) -> isize {
+        self.instrs.last().map_or(self.start, |(addr, instr)| addr + instr.width())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<isize, Block>
+}
+
+// Whether a jump with this condition can be taken, and whether it can fall through.
+fn outcomes(instr: &Instruction) -> (bool, bool) {
+    match *instr {
+        JumpIfTrue(Parameter::Immediate(v), _) => (v != 0, v == 0),
+        JumpIfFalse(Parameter::Immediate(v), _) => (v == 0, v != 0),
+        _ => (true, true)
+    }
+}
+
+/*
+   Code is found the same way the disassembler finds it.  A block starts at an entry point, at
+   the target of a resolved jump, and right after any jump or halt; it ends at the next jump or
+   halt, or just before the next block starts.
+ */
+pub fn cfg(program: &[isize]) -> Cfg {
+    let code = disassemble(program).lines.into_iter()
+        .filter_map(|line| match line {
+            Line::Code { addr, instr, .. } => Some((addr, instr)),
+            Line::Data { .. } => None
+        })
+        .collect_vec();
+
+    let addrs: BTreeSet<isize> = code.iter().map(|&(addr, _)| addr).collect();
+
+    let mut leaders = BTreeSet::from([0]);
+    for &(addr, instr) in code.iter() {
+        match instr {
+            JumpIfTrue(_, target) | JumpIfFalse(_, target) => {
+                if let Parameter::Immediate(target) = target {
+                    leaders.insert(target);
+                }
+                leaders.insert(addr + instr.width());
+            },
+            Done => { leaders.insert(addr + 1); },
+            _ => ()
+        }
+    }
+
+    let mut blocks: BTreeMap<isize, Block> = BTreeMap::new();
+    let mut current: Option<Block> = None;
+
+    for (addr, instr) in code {
+        let starts_block = match &current {
+            Some(block) => leaders.contains(&addr) || block.end() != addr,
+            None => true
+        };
+
+        if starts_block {
+            if let Some(block) = current.take() {
+                blocks.insert(block.start, block);
+            }
+            current = Some(Block { start: addr, instrs: Vec::new(), edges: Vec::new(), unresolved: false });
+        }
+
+        let Some(block) = current.as_mut() else { unreachable!() };
+        block.instrs.push((addr, instr));
+    }
+
+    if let Some(block) = current {
+        blocks.insert(block.start, block);
+    }
+
+    for block in blocks.values_mut() {
+        let Some(&(addr, instr)) = block.instrs.last() else { continue };
+        let next = addr + instr.width();
+
+        match instr {
+            JumpIfTrue(_, target) | JumpIfFalse(_, target) => {
+                let (taken, falls) = outcomes(&instr);
+
+                if taken {
+                    match target {
+                        Parameter::Immediate(target) if addrs.contains(&target) => block.edges.push(Edge::Taken(target)),
+                        Parameter::Immediate(_) => (),
+                        _ => block.unresolved = true
+                    }
+                }
+
+                if falls && addrs.contains(&next) {
+                    block.edges.push(Edge::Fallthrough(next));
+                }
+            },
+            Done => (),
+            _ if addrs.contains(&next) => block.edges.push(Edge::Fallthrough(next)),
+            _ => ()
+        }
+    }
+
+    Cfg { blocks }
+}
+
+impl Cfg {
+    /*
+       Graphviz source with one box per block.  Taken jumps are solid edges, fallthroughs are
+       dashed, and blocks ending in an unresolved jump point at a single `?` node.
+     */
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "digraph intcode {{");
+        let _ = writeln!(out, "    node [shape=box, fontname=\"monospace\"];");
+
+        for block in self.blocks.values() {
+            let label = block.instrs.iter()
+                .map(|(addr, instr)| format!("{addr:05}  {instr}\\l"))
+                .join("");
+            let _ = writeln!(out, "    b{} [label=\"{label}\"];", block.start);
+        }
+
+        for block in self.blocks.values() {
+            for edge in block.edges.iter() {
+                let style = match edge {
+                    Edge::Taken(_) => "solid",
+                    Edge::Fallthrough(_) => "dashed"
+                };
+                let _ = writeln!(out, "    b{} -> b{} [style={style}];", block.start, edge.target());
+            }
+        }
+
+        if self.blocks.values().any(|block| block.unresolved) {
+            let _ = writeln!(out, "    unresolved [label=\"?\", shape=circle];");
+            for block in self.blocks.values().filter(|block| block.unresolved) {
+                let _ = writeln!(out, "    b{} -> unresolved [style=dotted];", block.start);
+            }
+        }
+
+        let _ = writeln!(out, "}}");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+
+    #[test]
+    fn test_cfg() {
+        let program = assemble("
+                IN [n]
+        loop:   ADD [n], #-1, [n]
+                JT [n], #loop
+                ADD #ret, #0, rb+0
+                JT #1, #sub
+        ret:    HLT
+        sub:    OUT [n]
+                JF #0, rb+0
+        n:      .data 0
+        ").unwrap();
+
+        let graph = cfg(&program);
+        assert_eq!(graph.blocks.keys().copied().collect_vec(), vec![0, 2, 9, 16, 17]);
+
+        let edges = graph.blocks.values().map(|b| (b.start, b.edges.clone(), b.unresolved)).collect_vec();
+        assert_eq!(edges, vec![
+            (0, vec![Edge::Fallthrough(2)], false),
+            (2, vec![Edge::Taken(2), Edge::Fallthrough(9)], false),
+            (9, vec![Edge::Taken(17)], false),
+            (16, vec![], false),
+            (17, vec![], true)
+        ]);
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("    b2 [label=\"00002  ADD [22], #-1, [22]\\l00006  JT [22], #2\\l\"];\n"));
+        assert!(dot.contains("    b2 -> b9 [style=dashed];\n"));
+        assert!(dot.contains("    b17 -> unresolved [style=dotted];\n"));
+    }
+}
@@ -1,214 +1,343 @@
 use crate::intcode::cpu::Instruction::*;
-use crate::intcode::IntcodeError::{InputFailure, ParsingFailure, WriteToImmediate};
-use crate::intcode::IntcodeState::{AwaitingInput, Continue, Halted, OutputGenerated};
-use crate::intcode::{IntcodeError, IntcodeResult, IntcodeState, Resettable, Runnable};
-
-enum Instruction {
-    Add(Parameter, Parameter, Parameter),
-    Multiply(Parameter, Parameter, Parameter),
-    Input(Parameter),
-    Output(Parameter),
-    JumpIfTrue(Parameter, Parameter),
-    JumpIfFalse(Parameter, Parameter),
-    LessThan(Parameter, Parameter, Parameter),
-    Equal(Parameter, Parameter, Parameter),
-    RelativeBaseOffset(Parameter),
+use crate::intcode::IntcodeError::{BadAddress, InMachine, InputFailure, MemoryLimit, OutputLimit, Overflow, ParsingFailure, WriteToImmediate};
+use crate::intcode::IntcodeState::{AwaitingInput, Continue, Halted, OutOfSteps, OutputGenerated};
+use crate::intcode::{IntcodeError, IntcodeResult, IntcodeState, MachineContext, Resettable, Runnable};
+use crate::intcode::memory::{Memory, Paged, Storage};
+use crate::intcode::word::Word;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Instruction<W = isize> {
+    Add(Parameter<W>, Parameter<W>, Parameter<W>),
+    Multiply(Parameter<W>, Parameter<W>, Parameter<W>),
+    Input(Parameter<W>),
+    Output(Parameter<W>),
+    JumpIfTrue(Parameter<W>, Parameter<W>),
+    JumpIfFalse(Parameter<W>, Parameter<W>),
+    LessThan(Parameter<W>, Parameter<W>, Parameter<W>),
+    Equal(Parameter<W>, Parameter<W>, Parameter<W>),
+    RelativeBaseOffset(Parameter<W>),
     Done
 }
 
-enum Parameter {
-    Position(isize),
-    Immediate(isize),
-    Relative(isize)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Parameter<W = isize> {
+    Position(W),
+    Immediate(W),
+    Relative(W)
+}
+
+// How Add, Multiply and relative base adjustments treat results that don't fit in the word type.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Arithmetic {
+    #[default]
+    Checked,
+    Wrapping,
+    Saturating
 }
 
-pub struct Memory {
-    rom: Vec<isize>,
-    pub ram: Vec<isize>
+/*
+   Optional caps on a CPU.  Running out of steps stops the CPU with `OutOfSteps`, and it picks up
+   where it left off once more steps are allowed; going over the memory (in allocated words) or
+   output caps is an error.
+ */
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct Limits {
+    pub max_steps: Option<usize>,
+    pub max_memory: Option<usize>,
+    pub max_outputs: Option<usize>
 }
 
-impl Memory {
-    fn new(rom: Vec<isize>) -> Memory {
-        let ram = rom.clone();
-        Memory { rom, ram }
+impl<W: Clone> Instruction<W> {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Add(..) => "ADD",
+            Multiply(..) => "MUL",
+            Input(..) => "IN",
+            Output(..) => "OUT",
+            JumpIfTrue(..) => "JT",
+            JumpIfFalse(..) => "JF",
+            LessThan(..) => "LT",
+            Equal(..) => "EQ",
+            RelativeBaseOffset(..) => "ARB",
+            Done => "HLT"
+        }
     }
 
-    pub fn get(&self, address: isize) -> isize {
-        let i = address as usize;
-        if i >= self.ram.len() {
-            0
+    pub fn params(&self) -> Vec<Parameter<W>> {
+        match self {
+            Add(p1, p2, p3) | Multiply(p1, p2, p3) | LessThan(p1, p2, p3) | Equal(p1, p2, p3) => {
+                vec![p1.clone(), p2.clone(), p3.clone()]
+            },
+            JumpIfTrue(p1, p2) | JumpIfFalse(p1, p2) => vec![p1.clone(), p2.clone()],
+            Input(p1) | Output(p1) | RelativeBaseOffset(p1) => vec![p1.clone()],
+            Done => vec![]
         }
-        else {
-            self.ram[i]
+    }
+
+    pub fn width(&self) -> isize {
+        match self {
+            Add(..) | Multiply(..) | LessThan(..) | Equal(..) => 4,
+            JumpIfTrue(..) | JumpIfFalse(..) => 3,
+            Input(..) | Output(..) | RelativeBaseOffset(..) => 2,
+            Done => 1
         }
     }
 
-    pub fn set(&mut self, address: isize, value: isize) {
-        let i = address as usize;
-        if i >= self.ram.len() {
-            self.ram.resize(i + 1, 0);
+    pub fn target(&self) -> Option<Parameter<W>> {
+        match self {
+            Add(_, _, p3) | Multiply(_, _, p3) | LessThan(_, _, p3) | Equal(_, _, p3) => Some(p3.clone()),
+            Input(p1) => Some(p1.clone()),
+            _ => None
         }
-        self.ram[i] = value;
     }
+}
+
+pub fn to_address<W: Word>(word: &W) -> IntcodeResult<isize> {
+    word.to_isize().ok_or_else(|| BadAddress(word.to_string()))
+}
+
+fn param<W: Word, S: Storage<W>>(memory: &Memory<W, S>, addr: isize, modes: &W, i: usize) -> IntcodeResult<Parameter<W>> {
+    let x = memory.get(addr + i as isize)?;
+    let ten = W::from_u8(10).unwrap();
+    let mode = (modes.clone() / num::pow(ten.clone(), i - 1) % ten).to_isize().unwrap();
+    match mode {
+        0 => Ok(Parameter::Position(x)),
+        1 => Ok(Parameter::Immediate(x)),
+        2 => Ok(Parameter::Relative(x)),
+        _ => Err(IntcodeError::BadParameterMode(mode))
+    }
+}
+
+pub fn decode<W: Word, S: Storage<W>>(memory: &Memory<W, S>, addr: isize) -> IntcodeResult<Instruction<W>> {
+    let instr = memory.get(addr)?;
+    let hundred = W::from_u8(100).unwrap();
+    let op_code = (instr.clone() % hundred.clone()).to_isize().unwrap();
+    let modes = &(instr / hundred);
 
-    pub fn reset(&mut self) {
-        self.ram.clear();
-        self.ram.extend_from_slice(&self.rom);
+    match op_code {
+        1 => {
+            let p1 = param(memory, addr, modes, 1)?;
+            let p2 = param(memory, addr, modes, 2)?;
+            let p3 = param(memory, addr, modes, 3)?;
+            Ok(Add(p1, p2, p3))
+        },
+        2 => {
+            let p1 = param(memory, addr, modes, 1)?;
+            let p2 = param(memory, addr, modes, 2)?;
+            let p3 = param(memory, addr, modes, 3)?;
+            Ok(Multiply(p1, p2, p3))
+        },
+        3 => {
+            let p1 = param(memory, addr, modes, 1)?;
+            Ok(Input(p1))
+        },
+        4 => {
+            let p1 = param(memory, addr, modes, 1)?;
+            Ok(Output(p1))
+        },
+        5 => {
+            let p1 = param(memory, addr, modes, 1)?;
+            let p2 = param(memory, addr, modes, 2)?;
+            Ok(JumpIfTrue(p1, p2))
+        },
+        6 => {
+            let p1 = param(memory, addr, modes, 1)?;
+            let p2 = param(memory, addr, modes, 2)?;
+            Ok(JumpIfFalse(p1, p2))
+        },
+        7 => {
+            let p1 = param(memory, addr, modes, 1)?;
+            let p2 = param(memory, addr, modes, 2)?;
+            let p3 = param(memory, addr, modes, 3)?;
+            Ok(LessThan(p1, p2, p3))
+        },
+        8 => {
+            let p1 = param(memory, addr, modes, 1)?;
+            let p2 = param(memory, addr, modes, 2)?;
+            let p3 = param(memory, addr, modes, 3)?;
+            Ok(Equal(p1, p2, p3))
+        },
+        9 => {
+            let p1 = param(memory, addr, modes, 1)?;
+            Ok(RelativeBaseOffset(p1))
+        },
+        99 => Ok(Done),
+        _ => Err(IntcodeError::BadOpCode(op_code))
     }
 }
 
 pub fn parse_code(code: &str) -> IntcodeResult<Vec<isize>> {
+    parse_words(code)
+}
+
+pub fn parse_words<W: Word>(code: &str) -> IntcodeResult<Vec<W>> {
     code.split(',')
-        .map(|s| s.parse::<isize>().map_err(|e| ParsingFailure(e.to_string())))
+        .map(|s| W::from_str_radix(s, 10).map_err(|_| ParsingFailure(format!("invalid word `{s}`"))))
         .collect()
 }
 
-pub struct CPU {
-    pub memory: Memory,
+#[derive(Clone)]
+pub struct CPU<W: Word = isize, S: Storage<W> = Paged<W>> {
+    pub memory: Memory<W, S>,
     pub instr_ptr: isize,
     pub rel_base: isize,
-    pub input: Option<isize>
+    pub input: Option<W>,
+    pub arithmetic: Arithmetic,
+    pub limits: Limits,
+    pub steps: usize,
+    pub outputs: usize
 }
 
 impl CPU {
     pub fn new(program: Vec<isize>) -> CPU {
-        let memory = Memory::new(program);
+        CPU::with_storage(program)
+    }
+
+    pub fn parse(code: &str) -> IntcodeResult<CPU> {
+        let program = parse_code(code)?;
+        Ok(CPU::new(program))
+    }
+}
+
+impl<W: Word, S: Storage<W>> CPU<W, S> {
+    pub fn with_storage(program: Vec<W>) -> CPU<W, S> {
+        let memory = Memory::with_storage(program);
         let instr_ptr = 0;
         let rel_base = 0;
         let input = None;
+        let arithmetic = Arithmetic::default();
+        let limits = Limits::default();
 
-        CPU { memory, instr_ptr, rel_base, input }
+        CPU { memory, instr_ptr, rel_base, input, arithmetic, limits, steps: 0, outputs: 0 }
     }
 
-    pub fn parse(code: &str) -> IntcodeResult<CPU> {
-        let program = parse_code(code)?;
-        Ok(CPU::new(program))
+    pub fn parse_words(code: &str) -> IntcodeResult<CPU<W, S>> {
+        let program = parse_words(code)?;
+        Ok(CPU::with_storage(program))
     }
 
-    fn param(&self, i: usize) -> IntcodeResult<Parameter> {
-        let x = self.memory.get(self.instr_ptr + i as isize);
-        let mode = (self.memory.get(self.instr_ptr) / 10isize.pow((i + 1) as u32)) % 10;
-        match mode {
-            0 => Ok(Parameter::Position(x)),
-            1 => Ok(Parameter::Immediate(x)),
-            2 => Ok(Parameter::Relative(x)),
-            _ => Err(IntcodeError::BadParameterMode(mode))
-        }
+    pub fn with_arithmetic(mut self, arithmetic: Arithmetic) -> CPU<W, S> {
+        self.arithmetic = arithmetic;
+        self
     }
 
-    fn cur_instr(&self) -> IntcodeResult<Instruction> {
-        let instr = self.memory.get(self.instr_ptr);
-        let op_code = instr % 100;
+    pub fn with_limits(mut self, limits: Limits) -> CPU<W, S> {
+        self.limits = limits;
+        self
+    }
 
-        match op_code {
-            1 => {
-                let p1 = self.param(1)?;
-                let p2 = self.param(2)?;
-                let p3 = self.param(3)?;
-                Ok(Add(p1, p2, p3))
-            },
-            2 => {
-                let p1 = self.param(1)?;
-                let p2 = self.param(2)?;
-                let p3 = self.param(3)?;
-                Ok(Multiply(p1, p2, p3))
-            },
-            3 => {
-                let p1 = self.param(1)?;
-                Ok(Input(p1))
-            },
-            4 => {
-                let p1 = self.param(1)?;
-                Ok(Output(p1))
-            },
-            5 => {
-                let p1 = self.param(1)?;
-                let p2 = self.param(2)?;
-                Ok(JumpIfTrue(p1, p2))
-            },
-            6 => {
-                let p1 = self.param(1)?;
-                let p2 = self.param(2)?;
-                Ok(JumpIfFalse(p1, p2))
-            },
-            7 => {
-                let p1 = self.param(1)?;
-                let p2 = self.param(2)?;
-                let p3 = self.param(3)?;
-                Ok(LessThan(p1, p2, p3))
-            },
-            8 => {
-                let p1 = self.param(1)?;
-                let p2 = self.param(2)?;
-                let p3 = self.param(3)?;
-                Ok(Equal(p1, p2, p3))
-            },
-            9 => {
-                let p1 = self.param(1)?;
-                Ok(RelativeBaseOffset(p1))
-            },
-            99 => Ok(Done),
-            _ => Err(IntcodeError::BadOpCode(op_code))
+    // Lets the CPU run for `n` more steps from where it is now.
+    pub fn allow_steps(&mut self, n: usize) {
+        self.limits.max_steps = Some(self.steps + n);
+    }
+
+    pub fn cur_instr(&self) -> IntcodeResult<Instruction<W>> {
+        decode(&self.memory, self.instr_ptr)
+    }
+
+    pub fn address(&self, param: Parameter<W>) -> Option<isize> {
+        match param {
+            Parameter::Position(addr) => addr.to_isize(),
+            Parameter::Immediate(_) => None,
+            Parameter::Relative(offset) => self.rel_base.checked_add(offset.to_isize()?)
         }
     }
 
-    fn get(&self, param: Parameter) -> IntcodeResult<isize> {
+    fn relative(&self, offset: &W) -> IntcodeResult<isize> {
+        offset.to_isize()
+            .and_then(|o| self.rel_base.checked_add(o))
+            .ok_or(Overflow(self.instr_ptr))
+    }
+
+    pub fn get(&self, param: Parameter<W>) -> IntcodeResult<W> {
         match param {
-            Parameter::Position(addr) => Ok(self.memory.get(addr)),
+            Parameter::Position(addr) => self.memory.get(to_address(&addr)?),
             Parameter::Immediate(val) => Ok(val),
-            Parameter::Relative(offset) => Ok(self.memory.get(self.rel_base + offset))
+            Parameter::Relative(offset) => self.memory.get(self.relative(&offset)?)
         }
     }
 
-    fn set(&mut self, param: Parameter, value: isize) -> IntcodeResult<()> {
-        match param {
-            Parameter::Position(addr) => Ok(self.memory.set(addr, value)),
-            Parameter::Immediate(_) => Err(WriteToImmediate),
-            Parameter::Relative(offset) => Ok(self.memory.set(self.rel_base + offset, value))
+    fn set(&mut self, param: Parameter<W>, value: W) -> IntcodeResult<()> {
+        let addr = match param {
+            Parameter::Position(addr) => to_address(&addr)?,
+            Parameter::Immediate(_) => return Err(WriteToImmediate),
+            Parameter::Relative(offset) => self.relative(&offset)?
+        };
+
+        // Checked before the write, which could otherwise allocate far more than the limit.
+        let allocated = usize::try_from(addr).map(|a| self.memory.ram.allocated_with(a));
+        match self.limits.max_memory {
+            Some(max) if allocated.is_ok_and(|n| n > max) => Err(MemoryLimit(max)),
+            _ => self.memory.set(addr, value)
         }
     }
 }
 
-impl Resettable for CPU {
+impl<W: Word, S: Storage<W>> Resettable for CPU<W, S> {
     fn reset(&mut self) {
         self.memory.reset();
         self.instr_ptr = 0;
         self.rel_base = 0;
         self.input = None;
+        self.steps = 0;
+        self.outputs = 0;
     }
 }
 
-impl Runnable for CPU {
-    type Input = isize;
-    type Output = isize;
+impl<W: Word, S: Storage<W>> Runnable for CPU<W, S> {
+    type Input = W;
+    type Output = W;
 
     fn accept_input(&mut self, input: Self::Input) -> IntcodeResult<()> {
         match self.input {
             None => { self.input = Some(input); Ok(()) },
-            Some(_) => { Err(InputFailure) }
+            Some(_) => { Err(self.in_context(InputFailure)) }
+        }
+    }
+
+    fn step(&mut self) -> IntcodeResult<IntcodeState<W>> {
+        if self.limits.max_steps.is_some_and(|max| self.steps >= max) {
+            return Ok(OutOfSteps);
         }
+
+        let state = self.execute().map_err(|e| self.in_context(e))?;
+        if !matches!(state, AwaitingInput) {
+            self.steps += 1;
+        }
+        Ok(state)
+    }
+}
+
+impl<W: Word, S: Storage<W>> CPU<W, S> {
+    fn in_context(&self, error: IntcodeError) -> IntcodeError {
+        let instr = self.memory.get(self.instr_ptr)
+            .map_or("?".to_string(), |word| word.to_string());
+
+        let context = MachineContext { instr_ptr: self.instr_ptr, rel_base: self.rel_base, instr, steps: self.steps };
+        InMachine(Box::new(error), context)
     }
 
-    fn step(&mut self) -> IntcodeResult<IntcodeState<isize>> {
-        match self.cur_instr()? {
+    fn execute(&mut self) -> IntcodeResult<IntcodeState<W>> {
+        match self.memory.instruction(self.instr_ptr)? {
             Add(p1, p2, p3) => {
                 let v1 = self.get(p1)?;
                 let v2 = self.get(p2)?;
-                self.set(p3, v1 + v2)?;
+                let result = v1.add_with(&v2, self.arithmetic).ok_or(Overflow(self.instr_ptr))?;
+                self.set(p3, result)?;
                 self.instr_ptr += 4;
                 Ok(Continue)
             }
             Multiply(p1, p2, p3) => {
                 let v1 = self.get(p1)?;
                 let v2 = self.get(p2)?;
-                self.set(p3, v1 * v2)?;
+                let result = v1.mul_with(&v2, self.arithmetic).ok_or(Overflow(self.instr_ptr))?;
+                self.set(p3, result)?;
                 self.instr_ptr += 4;
                 Ok(Continue)
             }
             Input(p1) => {
-                match self.input {
+                match self.input.take() {
                     Some(val) => {
-                        self.input = None;
                         self.set(p1, val)?;
                         self.instr_ptr += 2;
                         Ok(Continue)
@@ -217,15 +346,20 @@ impl Runnable for CPU {
                 }
             }
             Output(p1) => {
+                if let Some(max) = self.limits.max_outputs.filter(|&max| self.outputs >= max) {
+                    return Err(OutputLimit(max));
+                }
+
                 let v1 = self.get(p1)?;
 
+                self.outputs += 1;
                 self.instr_ptr += 2;
                 Ok(OutputGenerated(v1))
             }
             JumpIfTrue(p1, p2) => {
                 let v1 = self.get(p1)?;
-                if v1 != 0 {
-                    self.instr_ptr = self.get(p2)?;
+                if !v1.is_zero() {
+                    self.instr_ptr = to_address(&self.get(p2)?)?;
                 }
                 else {
                     self.instr_ptr += 3;
@@ -234,8 +368,8 @@ impl Runnable for CPU {
             }
             JumpIfFalse(p1, p2) => {
                 let v1 = self.get(p1)?;
-                if v1 == 0 {
-                    self.instr_ptr = self.get(p2)?;
+                if v1.is_zero() {
+                    self.instr_ptr = to_address(&self.get(p2)?)?;
                 }
                 else {
                     self.instr_ptr += 3;
@@ -246,7 +380,7 @@ impl Runnable for CPU {
                 let v1 = self.get(p1)?;
                 let v2 = self.get(p2)?;
 
-                let result = if v1 < v2 { 1 } else { 0 };
+                let result = if v1 < v2 { W::one() } else { W::zero() };
                 self.set(p3, result)?;
                 self.instr_ptr += 4;
                 Ok(Continue)
@@ -254,14 +388,16 @@ impl Runnable for CPU {
             Equal(p1, p2, p3) => {
                 let v1 = self.get(p1)?;
                 let v2 = self.get(p2)?;
-                let result = if v1 == v2 { 1 } else { 0 };
+                let result = if v1 == v2 { W::one() } else { W::zero() };
                 self.set(p3, result)?;
                 self.instr_ptr += 4;
                 Ok(Continue)
             }
             RelativeBaseOffset(p1) => {
                 let v1 = self.get(p1)?;
-                self.rel_base += v1;
+                self.rel_base = v1.to_isize()
+                    .and_then(|v| self.rel_base.add_with(&v, self.arithmetic))
+                    .ok_or(Overflow(self.instr_ptr))?;
                 self.instr_ptr += 2;
                 Ok(Continue)
             }
@@ -270,4 +406,107 @@ impl Runnable for CPU {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::io::IOQueues;
+    use crate::intcode::memory::Flat;
+    use crate::intcode::IntcodeError::BadOpCode;
+    use num::BigInt;
+
+    #[test]
+    fn test_arithmetic() {
+        let program = vec![1002, 7, 2, 7, 4, 7, 99, isize::MAX];
+
+        let mut cpu = CPU::new(program.clone());
+        assert_eq!(cpu.step().unwrap_err().cause(), &Overflow(0));
+
+        let mut cpu = CPU::new(program.clone()).with_arithmetic(Arithmetic::Wrapping);
+        assert_eq!(cpu.run_until_output(), Ok(-2));
+
+        let mut cpu = CPU::new(program).with_arithmetic(Arithmetic::Saturating);
+        assert_eq!(cpu.run_until_output(), Ok(isize::MAX));
+    }
+
+    #[test]
+    fn test_words() {
+        // Square a number that overflows 64 bits.
+        let code = "2,7,7,7,4,7,99";
+        let program = format!("{code},{}", i64::MAX);
+
+        let mut cpu = CPU::<i64>::parse_words(&program).unwrap();
+        assert_eq!(cpu.run_until_output().unwrap_err().cause(), &Overflow(0));
+
+        let mut cpu = CPU::<i128>::parse_words(&program).unwrap();
+        assert_eq!(cpu.run_until_output(), Ok(i64::MAX as i128 * i64::MAX as i128));
+
+        let big = format!("{code},{}", i128::MAX);
+        let mut cpu = CPU::<BigInt>::parse_words(&big).unwrap();
+        assert_eq!(cpu.run_until_output(), Ok(BigInt::from(i128::MAX).pow(2)));
+
+        let expected = ParsingFailure(format!("invalid word `{}`", i64::MAX));
+        assert_eq!(CPU::<i32>::parse_words(&program).err(), Some(expected));
+    }
+
+    #[test]
+    fn test_fork() {
+        // Doubles its input.
+        let cpu = CPU::new(vec![3, 9, 1002, 9, 2, 9, 4, 9, 99, 0]);
+        let mut a = cpu.wrap(IOQueues::new());
+        a.step().unwrap();
+
+        let mut b = a.clone();
+        a.accept_input(5).unwrap();
+        b.accept_input(7).unwrap();
+        assert_eq!((a.run(), b.run()), (Ok(Halted), Ok(Halted)));
+
+        assert_eq!((a.outer.output, b.outer.output), ([10].into(), [14].into()));
+        assert_eq!((a.inner.memory.get(9), b.inner.memory.get(9)), (Ok(10), Ok(14)));
+        assert_eq!(a.inner.memory.rom(), b.inner.memory.rom());
+    }
+
+    #[test]
+    fn test_limits() {
+        // Counts up forever, printing each number.
+        let program = vec![1001, 9, 1, 9, 4, 9, 1105, 1, 0, 0];
+
+        let limits = Limits { max_steps: Some(7), ..Limits::default() };
+        let mut cpu = CPU::new(program.clone()).with_limits(limits);
+        assert_eq!(cpu.run(), Ok(OutOfSteps));
+        assert_eq!((cpu.steps, cpu.outputs, cpu.instr_ptr), (7, 2, 4));
+        cpu.allow_steps(1);
+        assert_eq!(cpu.run_until_output(), Ok(3));
+        assert_eq!(cpu.step(), Ok(OutOfSteps));
+
+        let limits = Limits { max_outputs: Some(3), ..Limits::default() };
+        let mut cpu = CPU::new(program).with_limits(limits);
+        assert_eq!(cpu.run().unwrap_err().cause(), &OutputLimit(3));
+
+        // The program fits in one page, but the write needs a second.
+        let limits = Limits { max_memory: Some(1024), ..Limits::default() };
+        let mut cpu = CPU::new(vec![1101, 1, 1, 5000, 99]).with_limits(limits);
+        assert_eq!(cpu.run().unwrap_err().cause(), &MemoryLimit(1024));
+
+        // A flat backend would have to allocate everything up to the address.
+        let limits = Limits { max_memory: Some(1 << 20), ..Limits::default() };
+        let mut cpu: CPU<isize, Flat> = CPU::with_storage(vec![1101, 1, 1, 1_000_000_000_000, 99]).with_limits(limits);
+        assert_eq!(cpu.run().unwrap_err().cause(), &MemoryLimit(1 << 20));
+        assert_eq!((cpu.memory.ram.allocated(), cpu.memory.get(1_000_000_000_000)), (5, Ok(0)));
+    }
+
+    #[test]
+    fn test_error_context() {
+        // Sets the relative base, then reads an input and runs into a bad opcode.
+        let mut cpu = CPU::new(vec![109, 3, 203, 0, 42]);
+        cpu.accept_input(7).unwrap();
+
+        let error = cpu.run().unwrap_err();
+        let context = MachineContext { instr_ptr: 4, rel_base: 3, instr: "42".to_string(), steps: 2 };
+        assert_eq!(error, InMachine(Box::new(BadOpCode(42)), context));
+        assert_eq!(error.to_string(), "bad opcode 42 (at 4: 42, relative base 3, after 2 steps)");
+
+        assert_eq!(cpu.accept_input(1).and(cpu.accept_input(2)).unwrap_err().cause(), &InputFailure);
+    }
+}
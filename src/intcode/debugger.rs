@@ -0,0 +1,343 @@
+use crate::intcode::calls::CallStack;
+use crate::intcode::cpu::{decode, CPU};
+use crate::intcode::disasm::disassemble_at;
+use crate::intcode::io::{IProvider, OProvider};
+use crate::intcode::memory::{Paged, Storage};
+use crate::intcode::IntcodeError::{InputFailure, LogicError};
+use crate::intcode::IntcodeState::{AwaitingInput, Continue, Halted, OutOfSteps, OutputGenerated};
+use crate::intcode::{IOWrapper, IntcodeError, IntcodeResult, Runnable};
+use itertools::Itertools;
+use std::collections::BTreeSet;
+use std::fmt::Debug;
+use std::io::{stdin, stdout, BufRead, Write};
+
+const HELP: &str = "\
+commands:
+  s, step [n]          execute n instructions (default 1)
+  c, continue          run until a breakpoint, watchpoint, halt or input wait
+  b, break <addr>      set a breakpoint
+  d, delete <addr>     remove a breakpoint or watchpoint
+  w, watch <addr>      break when <addr> is written
+  i, info              show registers, breakpoints and watchpoints
+  bt, backtrace        show the call stack
+  x <addr> [n]         show n memory words starting at <addr>
+  l, list [addr] [n]   disassemble n instructions (default: 8 from the current one)
+  set <addr> <value>   patch memory
+  q, quit              leave the debugger
+";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Stop {
+    Stepped,
+    Breakpoint(isize),
+    Watchpoint { addr: isize, old: isize, new: isize },
+    AwaitingInput,
+    OutOfSteps,
+    Halted
+}
+
+pub struct Debugger<IO: OProvider, S: Storage<isize> = Paged> {
+    pub system: IOWrapper<IO, CPU<isize, S>>,
+    pub breakpoints: BTreeSet<isize>,
+    pub watchpoints: BTreeSet<isize>,
+    pub outputs: Vec<IO::POutput>,
+    pub calls: CallStack
+}
+
+impl<IO, S> Debugger<IO, S>
+where IO: IProvider<PInput=isize> + OProvider<ROutput=isize>, S: Storage<isize> {
+    pub fn new(system: IOWrapper<IO, CPU<isize, S>>) -> Debugger<IO, S> {
+        let breakpoints = BTreeSet::new();
+        let watchpoints = BTreeSet::new();
+        let outputs = Vec::new();
+        let calls = CallStack::new();
+        Debugger { system, breakpoints, watchpoints, outputs, calls }
+    }
+
+    pub fn cpu(&self) -> &CPU<isize, S> {
+        &self.system.inner
+    }
+
+    pub fn step(&mut self) -> IntcodeResult<Stop> {
+        let cpu = &self.system.inner;
+        let ip = cpu.instr_ptr;
+
+        let watched = cpu.cur_instr().ok()
+            .and_then(|instr| instr.target())
+            .and_then(|param| cpu.address(param))
+            .filter(|addr| self.watchpoints.contains(addr))
+            .and_then(|addr| cpu.memory.get(addr).ok().map(|old| (addr, old)));
+
+        let pending = self.calls.before(cpu);
+        let state = self.system.step()?;
+        self.calls.after(pending, &self.system.inner);
+
+        match state {
+            OutputGenerated(o) => self.outputs.push(o),
+            AwaitingInput => return Ok(Stop::AwaitingInput),
+            OutOfSteps => return Ok(Stop::OutOfSteps),
+            Halted => return Ok(Stop::Halted),
+            Continue => ()
+        }
+
+        let cpu = &self.system.inner;
+
+        // An Input instruction that had to wait doesn't execute (or write) until a later step.
+        if cpu.instr_ptr == ip {
+            return Ok(Stop::Stepped);
+        }
+
+        if let Some((addr, old)) = watched {
+            let new = cpu.memory.get(addr)?;
+            return Ok(Stop::Watchpoint { addr, old, new });
+        }
+
+        if self.breakpoints.contains(&cpu.instr_ptr) {
+            return Ok(Stop::Breakpoint(cpu.instr_ptr));
+        }
+
+        Ok(Stop::Stepped)
+    }
+
+    pub fn resume(&mut self) -> IntcodeResult<Stop> {
+        loop {
+            match self.step()? {
+                Stop::Stepped => continue,
+                stop => return Ok(stop)
+            }
+        }
+    }
+
+    pub fn interact(&mut self) -> IntcodeResult<()>
+    where IO::POutput: Debug {
+        self.repl(stdin().lock(), stdout())
+    }
+
+    pub fn repl<R: BufRead, W: Write>(&mut self, mut input: R, mut out: W) -> IntcodeResult<()>
+    where IO::POutput: Debug {
+        self.show_location(&mut out)?;
+
+        loop {
+            write_out(&mut out, format_args!("(icdb) "))?;
+            out.flush().map_err(write_failure)?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line).map_err(|_| InputFailure)? == 0 {
+                return Ok(());
+            }
+
+            let words = line.split_whitespace().collect_vec();
+            if !self.execute(&words, &mut out)? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn execute<W: Write>(&mut self, words: &[&str], out: &mut W) -> IntcodeResult<bool>
+    where IO::POutput: Debug {
+        let args = words.iter().skip(1)
+            .map(|w| w.parse::<isize>())
+            .collect::<Result<Vec<isize>, _>>();
+
+        let Ok(args) = args else {
+            write_out(out, format_args!("expected numeric arguments\n"))?;
+            return Ok(true);
+        };
+
+        let Some(&command) = words.first() else { return Ok(true) };
+
+        match (command, &args[..]) {
+            ("s" | "step", [] | [_]) => {
+                let n = args.first().copied().unwrap_or(1);
+                let mut result = Ok(Stop::Stepped);
+
+                for _ in 0..n {
+                    result = self.step();
+                    if !matches!(result, Ok(Stop::Stepped)) {
+                        break;
+                    }
+                }
+
+                self.report(result, out)?;
+            },
+            ("c" | "continue", []) => {
+                let result = self.resume();
+                self.report(result, out)?;
+            },
+            ("b" | "break", &[addr]) => {
+                self.breakpoints.insert(addr);
+            },
+            ("w" | "watch", &[addr]) => {
+                self.watchpoints.insert(addr);
+            },
+            ("d" | "delete", &[addr]) => {
+                if !self.breakpoints.remove(&addr) && !self.watchpoints.remove(&addr) {
+                    write_out(out, format_args!("nothing set at {addr}\n"))?;
+                }
+            },
+            ("i" | "info", []) => {
+                let cpu = self.cpu();
+                write_out(out, format_args!(
+                    "ip={} rb={} input={:?}\nbreakpoints: {:?}\nwatchpoints: {:?}\n",
+                    cpu.instr_ptr, cpu.rel_base, cpu.input, self.breakpoints, self.watchpoints
+                ))?;
+            },
+            ("bt" | "backtrace", []) => {
+                let backtrace = self.calls.backtrace(self.cpu().instr_ptr);
+                write_out(out, format_args!("{backtrace}\n"))?;
+            },
+            ("x", &[addr]) | ("x", &[addr, _]) => {
+                let n = args.get(1).copied().unwrap_or(1);
+                let memory = &self.cpu().memory;
+                let Some(end) = addr.checked_add(n) else {
+                    write_out(out, format_args!("address out of range\n"))?;
+                    return Ok(true);
+                };
+
+                for a in addr..end {
+                    match memory.get(a) {
+                        Ok(v) => write_out(out, format_args!("{a:05}  {v}\n"))?,
+                        Err(e) => write_out(out, format_args!("{a:05}  error: {e}\n"))?
+                    }
+                }
+            },
+            ("l" | "list", [] | [_] | [_, _]) => {
+                let mut addr = args.first().copied().unwrap_or(self.cpu().instr_ptr);
+                let n = args.get(1).copied().unwrap_or(8);
+                let memory = &self.cpu().memory;
+
+                for _ in 0..n {
+                    write_out(out, format_args!("{addr:05}  {}\n", disassemble_at(memory, addr)))?;
+                    let width = decode(memory, addr).map(|instr| instr.width()).unwrap_or(1);
+                    match addr.checked_add(width) {
+                        Some(next) => addr = next,
+                        None => break
+                    }
+                }
+            },
+            ("set", &[addr, value]) => {
+                if let Err(e) = self.system.inner.memory.set(addr, value) {
+                    write_out(out, format_args!("error: {e}\n"))?;
+                }
+            },
+            ("h" | "help", []) => write_out(out, format_args!("{HELP}"))?,
+            ("q" | "quit", []) => return Ok(false),
+            _ => write_out(out, format_args!("unrecognized command; try `help`\n"))?
+        }
+
+        Ok(true)
+    }
+
+    fn report<W: Write>(&mut self, result: IntcodeResult<Stop>, out: &mut W) -> IntcodeResult<()>
+    where IO::POutput: Debug {
+        for o in self.outputs.drain(..) {
+            write_out(out, format_args!("output: {o:?}\n"))?;
+        }
+
+        match result {
+            Ok(Stop::Stepped) => (),
+            Ok(Stop::Breakpoint(addr)) => write_out(out, format_args!("breakpoint at {addr}\n"))?,
+            Ok(Stop::Watchpoint { addr, old, new }) => {
+                write_out(out, format_args!("watchpoint: [{addr}] {old} -> {new}\n"))?
+            },
+            Ok(Stop::AwaitingInput) => write_out(out, format_args!("awaiting input\n"))?,
+            Ok(Stop::OutOfSteps) => write_out(out, format_args!("step limit reached\n"))?,
+            Ok(Stop::Halted) => write_out(out, format_args!("halted\n"))?,
+            Err(e) => {
+                let backtrace = self.calls.backtrace(self.cpu().instr_ptr);
+                write_out(out, format_args!("error: {e}\n{backtrace}\n"))?
+            }
+        }
+
+        self.show_location(out)
+    }
+
+    fn show_location<W: Write>(&self, out: &mut W) -> IntcodeResult<()> {
+        let cpu = self.cpu();
+        let ip = cpu.instr_ptr;
+        write_out(out, format_args!("=> {ip:05}  {}\n", disassemble_at(&cpu.memory, ip)))
+    }
+}
+
+fn write_failure(e: std::io::Error) -> IntcodeError {
+    LogicError(format!("Failed to write debugger output: {e}"))
+}
+
+fn write_out<W: Write>(out: &mut W, args: std::fmt::Arguments) -> IntcodeResult<()> {
+    out.write_fmt(args).map_err(write_failure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::io::IOQueues;
+
+    const COUNTDOWN: &str = "
+            IN [x]
+    loop:   ADD [x], #-1, [x]
+            OUT [x]
+            JT [x], #loop
+            HLT
+    x:      .data 0
+    ";
+
+    #[test]
+    fn test_repl() {
+        let mut io = IOQueues::new();
+        io.input.push_back(2);
+        let cpu = CPU::new(assemble(COUNTDOWN).unwrap());
+        let mut debugger = Debugger::new(cpu.wrap(io));
+
+        // Watch the counter being read in, then step and patch it at the breakpoint on OUT.
+        let script = "b 6\nw 12\nc\nd 12\nc\ns\nset 12 5\nx 12\ns 2\ni\nq\nc\n";
+        let mut out = Vec::new();
+        debugger.repl(script.as_bytes(), &mut out).unwrap();
+
+        let expected = "\
+=> 00000  IN [12]
+(icdb) (icdb) (icdb) watchpoint: [12] 0 -> 2
+=> 00002  ADD [12], #-1, [12]
+(icdb) (icdb) breakpoint at 6
+=> 00006  OUT [12]
+(icdb) output: 1
+=> 00008  JT [12], #2
+(icdb) (icdb) 00012  5
+(icdb) breakpoint at 6
+=> 00006  OUT [12]
+(icdb) ip=6 rb=0 input=None
+breakpoints: {6}
+watchpoints: {}
+(icdb) ";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        // The patched counter was decremented, and nothing after `q` ran.
+        assert_eq!(debugger.cpu().memory.get(12), Ok(4));
+        assert_eq!(debugger.system.outer.output, [1]);
+        assert_eq!(debugger.step(), Ok(Stop::Stepped));
+        assert_eq!(debugger.system.outer.output, [1, 4]);
+    }
+
+    #[test]
+    fn test_bad_addresses() {
+        let cpu = CPU::new(vec![1, 0, 0, 0, 42]);
+        let mut debugger = Debugger::new(cpu.wrap(IOQueues::new()));
+
+        let script = "x -1\nx 9223372036854775807 2\nl 9223372036854775806 3\nset -2 0\ns 2\n";
+        let mut out = Vec::new();
+        debugger.repl(script.as_bytes(), &mut out).unwrap();
+
+        let expected = "\
+=> 00000  ADD [0], [0], [0]
+(icdb) -0001  error: negative address -1
+(icdb) address out of range
+(icdb) 9223372036854775806  .data 0
+9223372036854775807  .data 0
+(icdb) error: negative address -2
+(icdb) error: bad opcode 42 (at 4: 42, relative base 0, after 1 steps)
+#0  00004  in 00000
+=> 00004  .data 42
+(icdb) ";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::intcode::cpu::Instruction::{Done, JumpIfFalse, JumpIfTrue};
+use crate::intcode::cpu::{decode, Instruction, Parameter};
+use crate::intcode::memory::{Memory, Storage};
+use crate::intcode::word::Word;
+use itertools::Itertools;
+use std::collections::{BTreeSet, VecDeque};
+use num::Signed;
+use std::fmt::{Display, Formatter};
+
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Line<W = isize> {
+    Code { addr: isize, raw: Vec<W>, instr: Instruction<W> },
+    Data { addr: isize, words: Vec<W> }
+}
+
+impl<W> Line<W> {
+    pub fn addr(&self) -> isize {
+        match self {
+            Line::Code { addr, .. } | Line::Data { addr, .. } => *addr
+        }
+    }
+}
+
+pub struct Listing<W = isize> {
+    pub lines: Vec<Line<W>>
+}
+
+impl<W: Display + Signed> Display for Parameter<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Parameter::Position(addr) => write!(f, "[{addr}]"),
+            Parameter::Immediate(val) => write!(f, "#{val}"),
+            Parameter::Relative(offset) if offset.is_negative() => write!(f, "rb{offset}"),
+            Parameter::Relative(offset) => write!(f, "rb+{offset}")
+        }
+    }
+}
+
+impl<W: Display + Signed + Clone> Display for Instruction<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let params = self.params();
+        if params.is_empty() {
+            write!(f, "{}", self.mnemonic())
+        }
+        else {
+            write!(f, "{} {}", self.mnemonic(), params.iter().join(", "))
+        }
+    }
+}
+
+impl<W: Display + Signed + Clone> Display for Line<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Line::Code { addr, raw, instr } => {
+                write!(f, "{addr:05}  {:<24}  {instr}", raw.iter().join(","))
+            },
+            Line::Data { addr, words } => {
+                write!(f, "{addr:05}  {:<24}  .data {}", "", words.iter().join(", "))
+            }
+        }
+    }
+}
+
+impl<W: Display + Signed + Clone> Display for Listing<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for line in self.lines.iter() {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+/*
+   Code is found by following control flow from the entry points: every instruction falls through
+   to the next one (so the return address after a call is picked up), and jumps with an immediate
+   target are followed as well.  Anything never reached this way is listed as data.
+ */
+pub fn disassemble<W: Word>(program: &[W]) -> Listing<W> {
+    disassemble_from(program, &[0])
+}
+
+pub fn disassemble_from<W: Word>(program: &[W], entries: &[isize]) -> Listing<W> {
+    let memory: Memory<W> = Memory::with_storage(program.to_vec());
+    let len = program.len() as isize;
+
+    let mut code = BTreeSet::new();
+    let mut queue: VecDeque<isize> = entries.iter().copied().collect();
+
+    while let Some(addr) = queue.pop_front() {
+        if addr < 0 || addr >= len || code.contains(&addr) {
+            continue;
+        }
+
+        let Ok(instr) = decode(&memory, addr) else { continue };
+        if addr + instr.width() > len {
+            continue;
+        }
+
+        code.insert(addr);
+
+        match &instr {
+            Done => (),
+            JumpIfTrue(_, Parameter::Immediate(target)) | JumpIfFalse(_, Parameter::Immediate(target)) => {
+                queue.extend(target.to_isize());
+                queue.push_back(addr + instr.width());
+            },
+            _ => queue.push_back(addr + instr.width())
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut addr = 0;
+
+    while addr < len {
+        let instr = match code.contains(&addr) {
+            true => decode(&memory, addr).ok(),
+            false => None
+        };
+
+        match instr {
+            Some(instr) => {
+                let width = instr.width();
+                let raw = program[addr as usize..(addr + width) as usize].to_vec();
+                lines.push(Line::Code { addr, raw, instr });
+                addr += width;
+            },
+            None => {
+                let word = program[addr as usize].clone();
+                match lines.last_mut() {
+                    Some(Line::Data { words, .. }) if words.len() < DATA_PER_LINE => words.push(word),
+                    _ => lines.push(Line::Data { addr, words: vec![word] })
+                }
+                addr += 1;
+            }
+        }
+    }
+
+    Listing { lines }
+}
+
+pub fn disassemble_at<W: Word, S: Storage<W>>(memory: &Memory<W, S>, addr: isize) -> String {
+    match (decode(memory, addr), memory.get(addr)) {
+        (Ok(instr), _) => instr.to_string(),
+        (Err(_), Ok(word)) => format!(".data {word}"),
+        (Err(_), Err(_)) => "??".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::cpu::CPU;
+    use crate::intcode::IntcodeError::BadOpCode;
+
+    #[test]
+    fn test_listing() {
+        // The word at 13 is skipped by the jump, and everything after the HLT is never reached.
+        let mut program = vec![1101, 2, -3, 13, 204, -1, 22101, 7, 3, 1, 1105, 1, 14, 42, 99];
+        program.extend(1..=10);
+
+        let expected = "\
+00000  1101,2,-3,13              ADD #2, #-3, [13]
+00004  204,-1                    OUT rb-1
+00006  22101,7,3,1               ADD #7, rb+3, rb+1
+00010  1105,1,14                 JT #1, #14
+00013                            .data 42
+00014  99                        HLT
+00015                            .data 1, 2, 3, 4, 5, 6, 7, 8
+00023                            .data 9, 10
+";
+        let listing = disassemble(&program);
+        assert_eq!(listing.to_string(), expected);
+
+        // Every instruction listed is the one the CPU would execute there.
+        let mut cpu = CPU::new(program);
+        for line in listing.lines {
+            if let Line::Code { addr, instr, .. } = line {
+                cpu.instr_ptr = addr;
+                assert_eq!(cpu.cur_instr(), Ok(instr));
+            }
+        }
+
+        cpu.instr_ptr = 13;
+        assert_eq!(cpu.cur_instr(), Err(BadOpCode(42)));
+        assert_eq!(disassemble_at(&cpu.memory, 13), ".data 42");
+
+        // Data that happens to decode is still listed as data, but shows as code when asked for.
+        assert_eq!(disassemble_at(&cpu.memory, 15), "ADD [2], [3], [4]");
+    }
+}
@@ -1,7 +1,9 @@
 use crate::intcode::IntcodeState::{AwaitingInput, Continue, OutputGenerated};
 use crate::intcode::{IntcodeResult, IntcodeState, Resettable};
 use std::collections::VecDeque;
+use std::marker::PhantomData;
 use crate::intcode::IntcodeError::LogicError;
+use crate::intcode::word::Word;
 
 pub trait IProvider {
     type PInput;
@@ -19,6 +21,7 @@ pub trait OProvider {
     fn handle_output(&mut self, output: Self::ROutput) -> IntcodeResult<IntcodeState<Self::POutput>>;
 }
 
+#[derive(Clone)]
 pub struct Bus<I, O> { pub input: I, pub output: O }
 
 impl<IP: IProvider, OP> IProvider for Bus<IP, OP> {
@@ -50,22 +53,29 @@ impl<IP: Resettable, OP: Resettable> Resettable for Bus<IP, OP> {
     }
 }
 
-pub struct IOQueues {
-    pub input: VecDeque<isize>,
-    pub output: VecDeque<isize>
+#[derive(Clone)]
+pub struct IOQueues<W = isize> {
+    pub input: VecDeque<W>,
+    pub output: VecDeque<W>
 }
 
 impl IOQueues {
     pub fn new() -> IOQueues {
+        IOQueues::default()
+    }
+}
+
+impl<W> Default for IOQueues<W> {
+    fn default() -> IOQueues<W> {
         IOQueues { input: VecDeque::new(), output: VecDeque::new() }
     }
 }
 
-impl IProvider for IOQueues {
-    type PInput = isize;
-    type RInput = isize;
+impl<W: Word> IProvider for IOQueues<W> {
+    type PInput = W;
+    type RInput = W;
 
-    fn provide_input<O>(&mut self) -> IntcodeResult<(IntcodeState<O>, Option<isize>)> {
+    fn provide_input<O>(&mut self) -> IntcodeResult<(IntcodeState<O>, Option<W>)> {
         match self.input.pop_front() {
             s if s.is_some() => Ok((Continue, s)),
             _ => Ok((AwaitingInput, None))
@@ -78,23 +88,24 @@ impl IProvider for IOQueues {
     }
 }
 
-impl OProvider for IOQueues {
-    type POutput = isize;
-    type ROutput = isize;
+impl<W: Word> OProvider for IOQueues<W> {
+    type POutput = W;
+    type ROutput = W;
 
-    fn handle_output(&mut self, output: isize) -> IntcodeResult<IntcodeState<isize>> {
-        self.output.push_back(output);
+    fn handle_output(&mut self, output: W) -> IntcodeResult<IntcodeState<W>> {
+        self.output.push_back(output.clone());
         Ok(OutputGenerated(output))
     }
 }
 
-impl Resettable for IOQueues {
+impl<W> Resettable for IOQueues<W> {
     fn reset(&mut self) {
         self.input.clear();
         self.output.clear();
     }
 }
 
+#[derive(Clone)]
 pub struct ConstInput<T>(pub T);
 
 impl<T: Clone> IProvider for ConstInput<T> {
@@ -110,6 +121,7 @@ impl<T: Clone> IProvider for ConstInput<T> {
     }
 }
 
+#[derive(Clone)]
 pub struct Last<T>(pub Option<T>);
 
 impl<T> OProvider for Last<T> {
@@ -122,9 +134,9 @@ impl<T> OProvider for Last<T> {
     }
 }
 
-impl IProvider for VecDeque<isize> {
-    type PInput = isize;
-    type RInput = isize;
+impl<W: Word> IProvider for VecDeque<W> {
+    type PInput = W;
+    type RInput = W;
 
     fn provide_input<O>(&mut self) -> IntcodeResult<(IntcodeState<O>, Option<Self::PInput>)> {
         let result = self.pop_front();
@@ -138,11 +150,11 @@ impl IProvider for VecDeque<isize> {
     }
 }
 
-impl OProvider for VecDeque<isize> {
+impl<W: Word> OProvider for VecDeque<W> {
     type POutput = ();
-    type ROutput = isize;
+    type ROutput = W;
 
-    fn handle_output(&mut self, output: isize) -> IntcodeResult<IntcodeState<()>> {
+    fn handle_output(&mut self, output: W) -> IntcodeResult<IntcodeState<()>> {
         self.push_back(output);
         Ok(Continue)
     }
@@ -171,4 +183,114 @@ impl<T> Resettable for VecDeque<T> {
     fn reset(&mut self) {
         self.clear()
     }
-}
\ No newline at end of file
+}
+
+/*
+   Providers for one-off I/O, so a solver doesn't need a struct of its own, e.g.
+
+       cpu.connect(from_fn(|| joystick), collect_into(&mut outputs))
+
+   An iterator input makes the machine wait for input once it runs out, and `std::iter::from_fn`
+   turns a closure returning an `Option` into one.
+ */
+
+#[derive(Clone)]
+pub struct FromFn<F>(pub F);
+
+pub fn from_fn<T, F: FnMut() -> T>(f: F) -> FromFn<F> {
+    FromFn(f)
+}
+
+impl<T, F: FnMut() -> T> IProvider for FromFn<F> {
+    type PInput = T;
+    type RInput = ();
+
+    fn provide_input<O>(&mut self) -> IntcodeResult<(IntcodeState<O>, Option<T>)> {
+        Ok((Continue, Some((self.0)())))
+    }
+
+    fn receive_input(&mut self, _: Self::RInput) -> IntcodeResult<()> {
+        Err(LogicError("Does not accept input".to_string()))
+    }
+}
+
+#[derive(Clone)]
+pub struct FromIter<I>(pub I);
+
+pub fn from_iter<I: IntoIterator>(iter: I) -> FromIter<I::IntoIter> {
+    FromIter(iter.into_iter())
+}
+
+impl<I: Iterator> IProvider for FromIter<I> {
+    type PInput = I::Item;
+    type RInput = ();
+
+    fn provide_input<O>(&mut self) -> IntcodeResult<(IntcodeState<O>, Option<I::Item>)> {
+        let result = self.0.next();
+        let state = if result.is_some() { Continue } else { AwaitingInput };
+        Ok((state, result))
+    }
+
+    fn receive_input(&mut self, _: Self::RInput) -> IntcodeResult<()> {
+        Err(LogicError("Does not accept input".to_string()))
+    }
+}
+
+// The output type is a parameter, as a closure or collection may take more than one.
+#[derive(Clone)]
+pub struct ForEach<F, T>(F, PhantomData<fn(T)>);
+
+pub fn for_each<T, F: FnMut(T)>(f: F) -> ForEach<F, T> {
+    ForEach(f, PhantomData)
+}
+
+impl<T, F: FnMut(T)> OProvider for ForEach<F, T> {
+    type POutput = ();
+    type ROutput = T;
+
+    fn handle_output(&mut self, output: T) -> IntcodeResult<IntcodeState<()>> {
+        (self.0)(output);
+        Ok(Continue)
+    }
+}
+
+pub struct CollectInto<'a, E, T>(&'a mut E, PhantomData<fn(T)>);
+
+pub fn collect_into<T, E: Extend<T>>(collection: &mut E) -> CollectInto<'_, E, T> {
+    CollectInto(collection, PhantomData)
+}
+
+impl<T, E: Extend<T>> OProvider for CollectInto<'_, E, T> {
+    type POutput = ();
+    type ROutput = T;
+
+    fn handle_output(&mut self, output: T) -> IntcodeResult<IntcodeState<()>> {
+        self.0.extend(std::iter::once(output));
+        Ok(Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::cpu::CPU;
+    use crate::intcode::IntcodeState::Halted;
+    use crate::intcode::Runnable;
+
+    #[test]
+    fn test_closures_and_iterators() {
+        // Outputs twice each input until it reads a zero.
+        let doubler = CPU::parse("3,15,1006,15,14,1002,15,2,15,4,15,1105,1,0,99,0").unwrap();
+
+        let mut outputs = Vec::new();
+        let state = doubler.clone().connect(from_iter([1, 2, 3]), collect_into(&mut outputs)).run();
+        assert_eq!(state, Ok(AwaitingInput));
+        assert_eq!(outputs, [2, 4, 6]);
+
+        let mut n = 3;
+        let mut sum = 0;
+        let state = doubler.connect(from_fn(|| { n -= 1; n }), for_each(|o| sum += o)).run();
+        assert_eq!(state, Ok(Halted));
+        assert_eq!(sum, 6);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::intcode::cpu::{decode, Instruction};
+use crate::intcode::IntcodeError::NegativeAddress;
+use crate::intcode::IntcodeResult;
+use crate::intcode::word::Word;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+pub trait Storage<W>: Clone {
+    fn from_words(words: &[W]) -> Self;
+
+    fn load(&self, address: usize) -> W;
+
+    fn store(&mut self, address: usize, value: W);
+
+    // Number of words actually backed by allocated memory.
+    fn allocated(&self) -> usize;
+
+    // What `allocated` would be after a store to `address`, without doing it.
+    fn allocated_with(&self, address: usize) -> usize;
+
+    // Runs of allocated words as (start address, words), in increasing address order.
+    fn segments(&self) -> Vec<(usize, &[W])>;
+}
+
+// One contiguous vector that grows to cover the highest address written.  Fastest, but a single
+// write far out allocates everything below it.
+#[derive(Clone)]
+pub struct Flat<W = isize>(Arc<Vec<W>>);
+
+impl<W: Word> Storage<W> for Flat<W> {
+    fn from_words(words: &[W]) -> Flat<W> {
+        Flat(Arc::new(words.to_vec()))
+    }
+
+    fn load(&self, address: usize) -> W {
+        self.0.get(address).cloned().unwrap_or_else(W::zero)
+    }
+
+    fn store(&mut self, address: usize, value: W) {
+        let ram = Arc::make_mut(&mut self.0);
+        if address >= ram.len() {
+            ram.resize(address + 1, W::zero());
+        }
+        ram[address] = value;
+    }
+
+    fn allocated(&self) -> usize {
+        self.0.len()
+    }
+
+    fn allocated_with(&self, address: usize) -> usize {
+        self.0.len().max(address.saturating_add(1))
+    }
+
+    fn segments(&self) -> Vec<(usize, &[W])> {
+        vec![(0, &self.0[..])]
+    }
+}
+
+const PAGE_BITS: usize = 10;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+const DENSE_PAGES: usize = 1 << 12;
+
+type Page<W> = Arc<[W; PAGE_SIZE]>;
+
+fn empty_page<W: Word>() -> Page<W> {
+    Arc::new(std::array::from_fn(|_| W::zero()))
+}
+
+// Fixed-size pages allocated on first write.  Pages in the first few million words are found by
+// direct indexing; anything beyond that lives in a map, so a program scribbling at huge addresses
+// only pays for the pages it touches.  Pages are shared between clones until written.
+#[derive(Clone)]
+pub struct Paged<W = isize> {
+    dense: Vec<Option<Page<W>>>,
+    sparse: BTreeMap<usize, Page<W>>,
+    count: usize
+}
+
+impl<W: Word> Paged<W> {
+    fn page(&self, index: usize) -> Option<&Page<W>> {
+        if index < DENSE_PAGES {
+            self.dense.get(index).and_then(|p| p.as_ref())
+        }
+        else {
+            self.sparse.get(&index)
+        }
+    }
+
+    fn page_mut(&mut self, index: usize) -> &mut [W; PAGE_SIZE] {
+        let page = if index < DENSE_PAGES {
+            if index >= self.dense.len() {
+                self.dense.resize(index + 1, None);
+            }
+            let slot = &mut self.dense[index];
+            if slot.is_none() {
+                self.count += 1;
+            }
+            slot.get_or_insert_with(empty_page)
+        }
+        else {
+            if !self.sparse.contains_key(&index) {
+                self.count += 1;
+            }
+            self.sparse.entry(index).or_insert_with(empty_page)
+        };
+
+        Arc::make_mut(page)
+    }
+
+    fn pages(&self) -> impl Iterator<Item=(usize, &Page<W>)> {
+        self.dense.iter().enumerate()
+            .filter_map(|(i, p)| p.as_ref().map(|p| (i, p)))
+            .chain(self.sparse.iter().map(|(&i, p)| (i, p)))
+    }
+}
+
+impl<W: Word> Storage<W> for Paged<W> {
+    fn from_words(words: &[W]) -> Paged<W> {
+        let mut paged = Paged { dense: Vec::new(), sparse: BTreeMap::new(), count: 0 };
+        for (i, chunk) in words.chunks(PAGE_SIZE).enumerate() {
+            paged.page_mut(i)[..chunk.len()].clone_from_slice(chunk);
+        }
+        paged
+    }
+
+    fn load(&self, address: usize) -> W {
+        match self.page(address >> PAGE_BITS) {
+            Some(page) => page[address & (PAGE_SIZE - 1)].clone(),
+            None => W::zero()
+        }
+    }
+
+    fn store(&mut self, address: usize, value: W) {
+        self.page_mut(address >> PAGE_BITS)[address & (PAGE_SIZE - 1)] = value;
+    }
+
+    fn allocated(&self) -> usize {
+        self.count * PAGE_SIZE
+    }
+
+    fn allocated_with(&self, address: usize) -> usize {
+        match self.page(address >> PAGE_BITS) {
+            Some(_) => self.allocated(),
+            None => self.allocated() + PAGE_SIZE
+        }
+    }
+
+    fn segments(&self) -> Vec<(usize, &[W])> {
+        self.pages()
+            .map(|(i, page)| (i << PAGE_BITS, &page[..]))
+            .collect()
+    }
+}
+
+/*
+   Instructions inside the rom's address range are decoded once and kept in `decoded`.  A write
+   drops every cached instruction that covers the written address, so self-modifying code sees
+   its own changes.  Clones share the cache until one of them changes it, which once the code has
+   been run through happens only when it modifies itself.
+ */
+#[derive(Clone)]
+pub struct Memory<W: Word = isize, S: Storage<W> = Paged<W>> {
+    pub(crate) rom: Arc<Vec<W>>,
+    initial: S,
+    pub(crate) ram: S,
+    decoded: Arc<Vec<Option<Instruction<W>>>>
+}
+
+impl Memory {
+    pub fn new(rom: Vec<isize>) -> Memory {
+        Memory::with_storage(rom)
+    }
+}
+
+impl<W: Word, S: Storage<W>> Memory<W, S> {
+    pub fn with_storage(rom: Vec<W>) -> Memory<W, S> {
+        let ram = S::from_words(&rom);
+        Memory::from_parts(rom, ram)
+    }
+
+    pub(crate) fn from_parts(rom: Vec<W>, ram: S) -> Memory<W, S> {
+        let initial = S::from_words(&rom);
+        let decoded = Arc::new(vec![None; rom.len()]);
+        Memory { rom: Arc::new(rom), initial, ram, decoded }
+    }
+
+    pub fn rom(&self) -> &[W] {
+        &self.rom
+    }
+
+    pub fn ram(&self) -> &S {
+        &self.ram
+    }
+
+    pub fn get(&self, address: isize) -> IntcodeResult<W> {
+        if address < 0 {
+            return Err(NegativeAddress(address));
+        }
+        Ok(self.ram.load(address as usize))
+    }
+
+    pub fn set(&mut self, address: isize, value: W) -> IntcodeResult<()> {
+        if address < 0 {
+            return Err(NegativeAddress(address));
+        }
+        self.ram.store(address as usize, value);
+        self.invalidate(address as usize);
+        Ok(())
+    }
+
+    // Like `decode`, but served from the cache when possible.
+    pub fn instruction(&mut self, address: isize) -> IntcodeResult<Instruction<W>> {
+        let Some(slot) = usize::try_from(address).ok().filter(|&a| a < self.decoded.len()) else {
+            return decode(self, address);
+        };
+
+        if let Some(instr) = &self.decoded[slot] {
+            return Ok(instr.clone());
+        }
+
+        let instr = decode(self, address)?;
+        Arc::make_mut(&mut self.decoded)[slot] = Some(instr.clone());
+        Ok(instr)
+    }
+
+    fn invalidate(&mut self, address: usize) {
+        let end = self.decoded.len().min(address + 1);
+        for start in address.saturating_sub(3)..end {
+            let covers = self.decoded[start].as_ref()
+                .is_some_and(|instr| start + instr.width() as usize > address);
+
+            if covers {
+                Arc::make_mut(&mut self.decoded)[start] = None;
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.ram = self.initial.clone();
+        self.decoded = Arc::new(vec![None; self.decoded.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::cpu::CPU;
+    use crate::intcode::Runnable;
+
+    #[test]
+    fn test_high_addresses() {
+        // Write to and read back from address 10^12.
+        let program = vec![1101, 7, 0, 1_000_000_000_000, 4, 1_000_000_000_000, 99];
+        let mut cpu = CPU::new(program);
+        assert_eq!(cpu.run_until_output(), Ok(7));
+        assert!(cpu.memory.ram.allocated() <= 2 * PAGE_SIZE);
+    }
+
+    #[test]
+    fn test_negative_address() {
+        let mut cpu = CPU::new(vec![4, -5, 99]);
+        assert_eq!(cpu.step().unwrap_err().cause(), &NegativeAddress(-5));
+
+        let mut memory: Memory<isize, Flat> = Memory::with_storage(vec![1, 2, 3]);
+        assert_eq!(memory.set(-1, 0), Err(NegativeAddress(-1)));
+        memory.set(5, 9).unwrap();
+        assert_eq!((memory.get(2), memory.get(5), memory.get(4)), (Ok(3), Ok(9), Ok(0)));
+        memory.reset();
+        assert_eq!(memory.get(5), Ok(0));
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        // out #5; patch the operand of that instruction to 9; jump back to it.
+        let program = vec![104, 5, 1101, 0, 9, 1, 1105, 1, 0];
+        let mut cpu = CPU::new(program);
+        assert_eq!(cpu.run_until_output(), Ok(5));
+        assert_eq!(cpu.run_until_output(), Ok(9));
+    }
+
+    #[test]
+    fn test_fork_shares_cache() {
+        // Counts up forever, printing each number.
+        let program = vec![1001, 9, 1, 9, 4, 9, 1105, 1, 0, 0];
+        let mut cpu = CPU::new(program);
+        cpu.run_until_output().unwrap();
+        cpu.run_until_output().unwrap();
+
+        let mut fork = cpu.clone();
+        assert_eq!((cpu.run_until_output(), fork.run_until_output()), (Ok(3), Ok(3)));
+        assert!(Arc::ptr_eq(&cpu.memory.decoded, &fork.memory.decoded));
+
+        // Patching the fork's code gives it a cache of its own.
+        fork.memory.set(2, 5).unwrap();
+        assert_eq!((cpu.run_until_output(), fork.run_until_output()), (Ok(4), Ok(8)));
+        assert!(!Arc::ptr_eq(&cpu.memory.decoded, &fork.memory.decoded));
+    }
+}
@@ -0,0 +1,355 @@
+use crate::intcode::cpu::CPU;
+use crate::intcode::io::{IProvider, OProvider};
+use crate::intcode::IntcodeError::LogicError;
+use crate::intcode::IntcodeState::{Continue, OutputGenerated};
+use crate::intcode::{IOWrapper, IntcodeResult, IntcodeState, Runnable};
+use itertools::Itertools;
+use std::collections::{BTreeMap, VecDeque};
+
+pub type Packet = (isize, isize);
+
+// Enough for nodes that send, if they are going to, straight after finding their queue empty.
+pub const IDLE_POLLS: usize = 2;
+
+/*
+   The network interface of one node.  It hands the node its address, then feeds it queued
+   packets one value at a time, or -1 when the queue is empty.
+ */
+#[derive(Clone)]
+pub struct Nic {
+    address: Option<isize>,
+    packets: VecDeque<Packet>,
+    started: bool,
+    empty_polls: usize,
+    output: Vec<isize>
+}
+
+impl Nic {
+    pub fn new(address: isize) -> Nic {
+        let packets = VecDeque::new();
+        let output = Vec::new();
+        Nic { address: Some(address), packets, started: false, empty_polls: 0, output }
+    }
+
+    // Whether the node has found its queue empty at least `polls` times in a row, without
+    // sending anything in between.
+    pub fn is_blocked(&self, polls: usize) -> bool {
+        self.packets.is_empty() && self.output.is_empty() && self.empty_polls >= polls
+    }
+}
+
+impl IProvider for Nic {
+    type PInput = isize;
+    type RInput = Packet;
+
+    fn provide_input<O>(&mut self) -> IntcodeResult<(IntcodeState<O>, Option<isize>)> {
+        if let Some(address) = self.address.take() {
+            return Ok((Continue, Some(address)));
+        }
+
+        match self.packets.front() {
+            Some(&(x, y)) => {
+                self.started = !self.started;
+                if self.started {
+                    Ok((Continue, Some(x)))
+                }
+                else {
+                    self.packets.pop_front();
+                    Ok((Continue, Some(y)))
+                }
+            },
+            None => {
+                self.empty_polls += 1;
+                Ok((Continue, Some(-1)))
+            }
+        }
+    }
+
+    fn receive_input(&mut self, packet: Packet) -> IntcodeResult<()> {
+        self.empty_polls = 0;
+        self.packets.push_back(packet);
+        Ok(())
+    }
+}
+
+impl OProvider for Nic {
+    type POutput = (isize, Packet);
+    type ROutput = isize;
+
+    fn handle_output(&mut self, output: isize) -> IntcodeResult<IntcodeState<(isize, Packet)>> {
+        self.empty_polls = 0;
+        self.output.push(output);
+
+        match self.output[..] {
+            [dest, x, y] => {
+                self.output.clear();
+                Ok(OutputGenerated((dest, (x, y))))
+            },
+            _ => Ok(Continue)
+        }
+    }
+}
+
+pub type Node = IOWrapper<Nic, CPU>;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Action {
+    Nothing,
+    Send(isize, Packet),
+    Finish(Packet)
+}
+
+// Something other than a node listening on a network address, such as the NAT.
+pub trait Device {
+    fn receive(&mut self, packet: Packet) -> IntcodeResult<Action>;
+
+    // Called whenever every node is blocked and no packets are waiting.
+    fn idle(&mut self) -> IntcodeResult<Action> {
+        Ok(Action::Nothing)
+    }
+}
+
+// Stops the network with the first packet it receives.
+pub struct Capture;
+
+impl Device for Capture {
+    fn receive(&mut self, packet: Packet) -> IntcodeResult<Action> {
+        Ok(Action::Finish(packet))
+    }
+}
+
+// Remembers the last packet it received and sends it to node 0 when the network is idle.  Stops
+// the network with the packet it would send when that has the same y as the last one it sent.
+#[derive(Default)]
+pub struct Nat {
+    pub last: Option<Packet>,
+    pub sent: Option<Packet>
+}
+
+impl Nat {
+    pub fn new() -> Nat {
+        Nat::default()
+    }
+}
+
+impl Device for Nat {
+    fn receive(&mut self, packet: Packet) -> IntcodeResult<Action> {
+        self.last = Some(packet);
+        Ok(Action::Nothing)
+    }
+
+    fn idle(&mut self) -> IntcodeResult<Action> {
+        let packet = self.last.ok_or(LogicError("Network is idle, but the NAT has no packet".to_string()))?;
+
+        match self.sent.replace(packet) {
+            Some((_, y)) if y == packet.1 => Ok(Action::Finish(packet)),
+            _ => Ok(Action::Send(0, packet))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Stats {
+    pub rounds: usize,
+    pub idle_rounds: usize,
+    pub sent: Vec<usize>,
+    pub received: Vec<usize>,
+    pub to_devices: BTreeMap<isize, usize>
+}
+
+impl Stats {
+    pub fn delivered(&self) -> usize {
+        self.received.iter().sum::<usize>() + self.to_devices.values().sum::<usize>()
+    }
+}
+
+/*
+   The network is idle once every node has an empty queue and has polled it `idle_polls` times in
+   a row, getting -1 each time and sending nothing since.  Only the queues are looked at, so a node
+   may do whatever it likes between polls, but a node that can find its queue empty that many
+   times and still go on to send something needs a higher `idle_polls`.
+ */
+pub struct Network {
+    pub nodes: Vec<Node>,
+    pub devices: BTreeMap<isize, Box<dyn Device>>,
+    pub stats: Stats,
+    pub idle_polls: usize
+}
+
+impl Network {
+    pub fn new(program: &[isize], size: usize) -> Network {
+        let nodes = (0..size)
+            .map(|address| CPU::new(program.to_vec()).wrap(Nic::new(address as isize)))
+            .collect_vec();
+
+        let stats = Stats { sent: vec![0; size], received: vec![0; size], ..Stats::default() };
+        Network { nodes, devices: BTreeMap::new(), stats, idle_polls: IDLE_POLLS }
+    }
+
+    pub fn with_idle_polls(mut self, polls: usize) -> Network {
+        self.idle_polls = polls;
+        self
+    }
+
+    pub fn with_device<D: Device + 'static>(mut self, address: isize, device: D) -> Network {
+        self.devices.insert(address, Box::new(device));
+        self
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.nodes.iter().all(|node| node.outer.is_blocked(self.idle_polls))
+    }
+
+    // Delivers a packet, returning the packet a device finished the network with, if any.
+    pub fn send(&mut self, dest: isize, packet: Packet) -> IntcodeResult<Option<Packet>> {
+        if let Some(node) = usize::try_from(dest).ok().and_then(|i| self.nodes.get_mut(i)) {
+            node.accept_input(packet)?;
+            self.stats.received[dest as usize] += 1;
+            return Ok(None);
+        }
+
+        let device = self.devices.get_mut(&dest).ok_or(LogicError(format!("Nothing at address {dest}")))?;
+        let action = device.receive(packet)?;
+        *self.stats.to_devices.entry(dest).or_default() += 1;
+        self.act(action)
+    }
+
+    fn act(&mut self, action: Action) -> IntcodeResult<Option<Packet>> {
+        match action {
+            Action::Nothing => Ok(None),
+            Action::Send(dest, packet) => self.send(dest, packet),
+            Action::Finish(packet) => Ok(Some(packet))
+        }
+    }
+
+    // Steps every node once, then wakes the devices if that left the network idle.
+    pub fn round(&mut self) -> IntcodeResult<Option<Packet>> {
+        self.stats.rounds += 1;
+
+        for i in 0..self.nodes.len() {
+            if let OutputGenerated((dest, packet)) = self.nodes[i].step()? {
+                self.stats.sent[i] += 1;
+                if let Some(packet) = self.send(dest, packet)? {
+                    return Ok(Some(packet));
+                }
+            }
+        }
+
+        if !self.is_idle() {
+            return Ok(None);
+        }
+
+        self.stats.idle_rounds += 1;
+
+        let addresses = self.devices.keys().copied().collect_vec();
+        let mut woken = false;
+
+        for address in addresses {
+            let action = self.devices.get_mut(&address).unwrap().idle()?;
+            woken |= action != Action::Nothing;
+            if let Some(packet) = self.act(action)? {
+                return Ok(Some(packet));
+            }
+        }
+
+        match woken {
+            true => Ok(None),
+            false => Err(LogicError("Network is idle and nothing woke it".to_string()))
+        }
+    }
+
+    pub fn run(&mut self) -> IntcodeResult<Packet> {
+        loop {
+            if let Some(packet) = self.round()? {
+                return Ok(packet);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+
+    // Passes every packet on to the next node; the last node sends it to 255.
+    const RELAY: &str = "
+            IN [me]
+    loop:   IN [x]
+            EQ [x], #-1, [t]
+            JT [t], #loop
+            IN [y]
+            ADD [me], #1, [dest]
+            EQ [dest], #3, [t]
+            JF [t], #send
+            ADD #255, #0, [dest]
+    send:   OUT [dest]
+            OUT [x]
+            OUT [y]
+            JT #1, #loop
+    me:     .data 0
+    x:      .data 0
+    y:      .data 0
+    t:      .data 0
+    dest:   .data 0
+    ";
+
+    #[test]
+    fn test_network() {
+        let program = assemble(RELAY).unwrap();
+
+        let mut network = Network::new(&program, 3).with_device(255, Capture);
+        network.send(0, (7, 8)).unwrap();
+        assert_eq!(network.run(), Ok((7, 8)));
+        assert_eq!(network.stats.sent, vec![1, 1, 1]);
+        assert_eq!(network.stats.delivered(), 4);
+
+        let mut network = Network::new(&program, 3).with_device(255, Nat::new());
+        network.send(1, (5, 6)).unwrap();
+        assert_eq!(network.run(), Ok((5, 6)));
+        assert_eq!(network.stats.received, vec![1, 2, 2]);
+        assert_eq!(network.stats.to_devices, BTreeMap::from([(255, 2)]));
+        assert_eq!(network.stats.idle_rounds, 2);
+
+        let mut network = Network::new(&program, 3).with_device(255, Nat::new());
+        assert!(matches!(network.run(), Err(LogicError(_))));
+    }
+
+    // Counts its empty polls forever, sending (address, 42) to 255 on the third, and ignores every
+    // packet it gets.
+    const PATIENT: &str = "
+            IN [me]
+    loop:   IN [x]
+            EQ [x], #-1, [t]
+            JF [t], #ignore
+            ADD [n], #1, [n]
+            EQ [n], #3, [t]
+            JF [t], #loop
+            OUT #255
+            OUT [me]
+            OUT #42
+            JT #1, #loop
+    ignore: IN [y]
+            JT #1, #loop
+    me:     .data 0
+    x:      .data 0
+    y:      .data 0
+    t:      .data 0
+    n:      .data 0
+    ";
+
+    #[test]
+    fn test_idle_polls() {
+        let program = assemble(PATIENT).unwrap();
+
+        // Two empty polls aren't enough to tell it's idle before it has sent anything...
+        let mut network = Network::new(&program, 2).with_device(255, Nat::new());
+        assert!(matches!(network.run(), Err(LogicError(_))));
+
+        // ...but a fourth is, even though its memory changes at every poll.
+        let mut network = Network::new(&program, 2).with_device(255, Nat::new()).with_idle_polls(4);
+        assert_eq!(network.run(), Ok((1, 42)));
+        assert_eq!(network.stats.sent, vec![1, 1]);
+        assert_eq!(network.stats.idle_rounds, 2);
+    }
+}
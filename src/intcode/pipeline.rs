@@ -0,0 +1,146 @@
+use crate::intcode::IntcodeState::{AwaitingInput, Continue, Halted, OutOfSteps, OutputGenerated};
+use crate::intcode::{IntcodeResult, IntcodeState, Resettable, Runnable};
+use std::collections::VecDeque;
+
+/*
+   Machines chained so that each one's outputs are the next one's inputs; with feedback, the last
+   one's outputs also go back to the first.  Input to the pipeline goes to the first stage, and
+   the last stage's outputs are both produced as the pipeline's outputs and kept in `outputs`.
+
+   A step runs one instruction of a stage that can make progress, staying on the same stage until
+   it halts or waits for input nobody has sent it yet.  The pipeline waits for input once every
+   stage that hasn't halted is waiting, and halts once they all have.
+ */
+pub struct Pipeline<R: Runnable> {
+    pub stages: Vec<R>,
+    pub outputs: Vec<R::Output>,
+    queues: Vec<VecDeque<R::Input>>,
+    waiting: Vec<bool>,
+    halted: Vec<bool>,
+    feedback: bool,
+    current: usize
+}
+
+impl<T: Clone, R: Runnable<Input=T, Output=T>> Pipeline<R> {
+    pub fn new(stages: Vec<R>) -> Pipeline<R> {
+        let n = stages.len();
+        let queues = (0..n).map(|_| VecDeque::new()).collect();
+
+        Pipeline {
+            stages,
+            outputs: Vec::new(),
+            queues,
+            waiting: vec![false; n],
+            halted: vec![false; n],
+            feedback: false,
+            current: 0
+        }
+    }
+
+    pub fn with_feedback(mut self) -> Pipeline<R> {
+        self.feedback = true;
+        self
+    }
+
+    // Queues an input for one stage, e.g. an amplifier's phase setting.
+    pub fn feed(&mut self, stage: usize, input: T) {
+        self.queues[stage].push_back(input);
+        self.waiting[stage] = false;
+    }
+
+    fn ready(&self, stage: usize) -> bool {
+        !self.halted[stage] && !self.waiting[stage]
+    }
+}
+
+impl<T: Clone, R: Runnable<Input=T, Output=T>> Runnable for Pipeline<R> {
+    type Input = T;
+    type Output = T;
+
+    fn accept_input(&mut self, input: T) -> IntcodeResult<()> {
+        self.feed(0, input);
+        Ok(())
+    }
+
+    fn step(&mut self) -> IntcodeResult<IntcodeState<T>> {
+        let n = self.stages.len();
+        let next = (0..n).map(|k| (self.current + k) % n).find(|&i| self.ready(i));
+
+        let Some(i) = next else {
+            return match self.halted.iter().all(|&h| h) {
+                true => Ok(Halted),
+                false => Ok(AwaitingInput)
+            };
+        };
+
+        self.current = i;
+
+        match self.stages[i].step()? {
+            OutputGenerated(o) if i + 1 < n => {
+                self.feed(i + 1, o);
+                Ok(Continue)
+            },
+            OutputGenerated(o) => {
+                if self.feedback {
+                    self.feed(0, o.clone());
+                }
+                self.outputs.push(o.clone());
+                Ok(OutputGenerated(o))
+            },
+            AwaitingInput => {
+                match self.queues[i].pop_front() {
+                    Some(input) => self.stages[i].accept_input(input)?,
+                    None => self.waiting[i] = true
+                }
+                Ok(Continue)
+            },
+            Halted => {
+                self.halted[i] = true;
+                Ok(Continue)
+            },
+            OutOfSteps => Ok(OutOfSteps),
+            Continue => Ok(Continue)
+        }
+    }
+}
+
+impl<T: Clone, R: Runnable<Input=T, Output=T> + Resettable> Resettable for Pipeline<R> {
+    fn reset(&mut self) {
+        for stage in self.stages.iter_mut() {
+            stage.reset();
+        }
+        for queue in self.queues.iter_mut() {
+            queue.clear();
+        }
+        self.outputs.clear();
+        self.waiting.fill(false);
+        self.halted.fill(false);
+        self.current = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::cpu::CPU;
+    use crate::intcode::io::IOQueues;
+
+    #[test]
+    fn test_pipeline() {
+        // Each stage reads a number, doubles it and passes it on.
+        let double = CPU::parse("3,9,1002,9,2,9,4,9,99,0").unwrap();
+        let mut system = Pipeline::new(vec![double; 3]).wrap(IOQueues::new());
+        system.accept_input(5).unwrap();
+        assert_eq!(system.run(), Ok(Halted));
+        assert_eq!(system.outer.output, [40]);
+
+        // Adds its input to a running total and passes it on, forever.
+        let total = CPU::parse("3,11,1,11,12,12,4,12,1105,1,0,0,0").unwrap();
+        let mut ring = Pipeline::new(vec![total; 2]).with_feedback();
+        ring.accept_input(1).unwrap();
+        for _ in 0..4 {
+            ring.run_until_output().unwrap();
+        }
+        assert_eq!(ring.outputs, [1, 3, 8, 21]);
+    }
+}
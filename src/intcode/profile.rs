@@ -0,0 +1,140 @@
+use crate::intcode::cpu::CPU;
+use crate::intcode::disasm::disassemble_at;
+use crate::intcode::memory::{Paged, Storage};
+use crate::intcode::word::Word;
+use crate::intcode::IntcodeState::{AwaitingInput, OutOfSteps};
+use crate::intcode::{IntcodeResult, IntcodeState, Resettable, Runnable};
+use itertools::Itertools;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Profile {
+    pub steps: usize,
+    pub hits: BTreeMap<isize, usize>,
+    pub opcodes: BTreeMap<&'static str, usize>,
+    pub waits: BTreeMap<isize, usize>
+}
+
+// Counts are kept across resets, so repeated runs of one program add up to a single profile.
+#[derive(Clone)]
+pub struct Profiled<W: Word = isize, S: Storage<W> = Paged<W>> {
+    pub cpu: CPU<W, S>,
+    pub profile: Profile
+}
+
+impl<W: Word, S: Storage<W>> Profiled<W, S> {
+    pub fn new(cpu: CPU<W, S>) -> Profiled<W, S> {
+        Profiled { cpu, profile: Profile::default() }
+    }
+
+    /*
+       Addresses from most to least executed, each with the instruction currently there, then the
+       same for opcodes, then the places the program waited for input.
+     */
+    pub fn report(&self) -> String {
+        let profile = &self.profile;
+        let percent = |n: usize| 100.0 * n as f64 / profile.steps.max(1) as f64;
+        let mut out = String::new();
+
+        let _ = writeln!(out, "{} steps", profile.steps);
+
+        let _ = writeln!(out, "\n addr        hits       %  instruction");
+        for (&addr, &n) in profile.hits.iter().sorted_by_key(|&(&addr, &n)| (Reverse(n), addr)) {
+            let instr = disassemble_at(&self.cpu.memory, addr);
+            let _ = writeln!(out, "{addr:05}  {n:>10}  {:>5.1}%  {instr}", percent(n));
+        }
+
+        let _ = writeln!(out, "\nopcode      hits       %");
+        for (op, &n) in profile.opcodes.iter().sorted_by_key(|&(&op, &n)| (Reverse(n), op)) {
+            let _ = writeln!(out, "{op:<6}{n:>10}  {:>5.1}%", percent(n));
+        }
+
+        if !profile.waits.is_empty() {
+            let _ = writeln!(out, "\n addr       waits  instruction");
+            for (&addr, &n) in profile.waits.iter().sorted_by_key(|&(&addr, &n)| (Reverse(n), addr)) {
+                let instr = disassemble_at(&self.cpu.memory, addr);
+                let _ = writeln!(out, "{addr:05}  {n:>10}  {instr}");
+            }
+        }
+
+        out
+    }
+}
+
+impl<W: Word, S: Storage<W>> CPU<W, S> {
+    pub fn profiled(self) -> Profiled<W, S> {
+        Profiled::new(self)
+    }
+}
+
+impl<W: Word, S: Storage<W>> Runnable for Profiled<W, S> {
+    type Input = W;
+    type Output = W;
+
+    fn accept_input(&mut self, input: W) -> IntcodeResult<()> {
+        self.cpu.accept_input(input)
+    }
+
+    fn step(&mut self) -> IntcodeResult<IntcodeState<W>> {
+        let ip = self.cpu.instr_ptr;
+        let mnemonic = self.cpu.cur_instr().map(|instr| instr.mnemonic());
+        let state = self.cpu.step()?;
+
+        match (&state, mnemonic) {
+            (AwaitingInput, _) => *self.profile.waits.entry(ip).or_default() += 1,
+            (OutOfSteps, _) => (),
+            (_, Ok(mnemonic)) => {
+                self.profile.steps += 1;
+                *self.profile.hits.entry(ip).or_default() += 1;
+                *self.profile.opcodes.entry(mnemonic).or_default() += 1;
+            },
+            (_, Err(_)) => ()
+        }
+
+        Ok(state)
+    }
+}
+
+impl<W: Word, S: Storage<W>> Resettable for Profiled<W, S> {
+    fn reset(&mut self) {
+        self.cpu.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::IntcodeState::Halted;
+
+    #[test]
+    fn test_profile() {
+        let program = assemble("
+                IN [n]
+        loop:   ADD [n], #-1, [n]
+                JT [n], #loop
+                OUT [n]
+                HLT
+        n:      .data 0
+        ").unwrap();
+
+        let mut profiled = CPU::new(program).profiled();
+        assert_eq!(profiled.run(), Ok(AwaitingInput));
+        profiled.accept_input(5).unwrap();
+        assert_eq!(profiled.run(), Ok(Halted));
+
+        let profile = &profiled.profile;
+        assert_eq!(profile.steps, 13);
+        assert_eq!(profile.hits, BTreeMap::from([(0, 1), (2, 5), (6, 5), (9, 1), (11, 1)]));
+        assert_eq!(profile.opcodes, BTreeMap::from([("ADD", 5), ("JT", 5), ("IN", 1), ("OUT", 1), ("HLT", 1)]));
+        assert_eq!(profile.waits, BTreeMap::from([(0, 1)]));
+
+        let report = profiled.report();
+        let lines = report.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "13 steps");
+        assert_eq!(lines[3], "00002           5   38.5%  ADD [12], #-1, [12]");
+        assert_eq!(lines.last(), Some(&"00000           1  IN [12]"));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::intcode::ascii::Ascii;
+use crate::intcode::IntcodeError::{InputFailure, LogicError};
+use crate::intcode::IntcodeState::{AwaitingInput, Continue, Halted, OutOfSteps, OutputGenerated};
+use crate::intcode::{IntcodeError, IntcodeResult, IntcodeState, Runnable};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{stdin, stdout, BufRead, BufReader, Write};
+use std::path::Path;
+
+/*
+   Runs an ASCII program, printing what it says and reading a command for it whenever it waits for
+   input.  Commands are taken from the replay queue first, echoed after a "> " so a replayed session
+   reads the same as a typed one, and from the terminal after that.  Every command sent is kept in
+   `transcript`, and also written to the recording file, if there is one.
+
+   A transcript file holds one command per line, so replaying just the first n lines of one and
+   carrying on by hand stops the replay at line n.
+ */
+pub struct Repl<M> {
+    pub system: M,
+    pub transcript: Vec<String>,
+    replay: VecDeque<String>,
+    recording: Option<File>
+}
+
+impl<M: Runnable<Input=String, Output=Ascii>> Repl<M> {
+    pub fn new(system: M) -> Repl<M> {
+        Repl { system, transcript: Vec::new(), replay: VecDeque::new(), recording: None }
+    }
+
+    pub fn with_replay(mut self, commands: Vec<String>) -> Repl<M> {
+        self.replay.extend(commands);
+        self
+    }
+
+    pub fn with_recording<P: AsRef<Path>>(mut self, path: P) -> IntcodeResult<Repl<M>> {
+        self.recording = Some(File::create(path).map_err(failure)?);
+        Ok(self)
+    }
+
+    pub fn interact(&mut self) -> IntcodeResult<IntcodeState<()>> {
+        self.repl(stdin().lock(), stdout())
+    }
+
+    // Returns once the program halts or runs out of steps, or waits for input after the replay
+    // queue and `input` have both run out.
+    pub fn repl<R: BufRead, W: Write>(&mut self, mut input: R, mut out: W) -> IntcodeResult<IntcodeState<()>> {
+        loop {
+            match self.system.step()? {
+                OutputGenerated(Ascii::Line(line)) => write_out(&mut out, format_args!("{line}\n"))?,
+                OutputGenerated(Ascii::Value(value)) => write_out(&mut out, format_args!("{value}\n"))?,
+                AwaitingInput => {
+                    let command = match self.replay.pop_front() {
+                        Some(command) => {
+                            write_out(&mut out, format_args!("> {command}\n"))?;
+                            command
+                        },
+                        None => {
+                            out.flush().map_err(failure)?;
+                            let mut line = String::new();
+                            if input.read_line(&mut line).map_err(|_| InputFailure)? == 0 {
+                                return Ok(AwaitingInput);
+                            }
+                            line.trim_end_matches(['\r', '\n']).to_string()
+                        }
+                    };
+
+                    self.send(command)?;
+                },
+                Halted => return Ok(Halted),
+                OutOfSteps => return Ok(OutOfSteps),
+                Continue => ()
+            }
+        }
+    }
+
+    pub fn send(&mut self, command: String) -> IntcodeResult<()> {
+        if let Some(file) = self.recording.as_mut() {
+            writeln!(file, "{command}").map_err(failure)?;
+        }
+
+        self.system.accept_input(format!("{command}\n"))?;
+        self.transcript.push(command);
+        Ok(())
+    }
+}
+
+pub fn read_transcript<P: AsRef<Path>>(path: P) -> IntcodeResult<Vec<String>> {
+    let file = File::open(path).map_err(failure)?;
+    BufReader::new(file).lines()
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(failure)
+}
+
+fn failure(e: std::io::Error) -> IntcodeError {
+    LogicError(format!("Transcript I/O failed: {e}"))
+}
+
+fn write_out<W: Write>(out: &mut W, args: std::fmt::Arguments) -> IntcodeResult<()> {
+    out.write_fmt(args).map_err(failure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::ascii::AsciiIO;
+    use crate::intcode::cpu::CPU;
+
+    #[test]
+    fn test_record_and_replay() {
+        // Echoes its input forever.
+        let echo = CPU::parse("3,7,4,7,1105,1,0,0").unwrap();
+        let path = std::env::temp_dir().join(format!("intcode-repl-{}.txt", std::process::id()));
+
+        let mut repl = Repl::new(echo.clone().wrap(AsciiIO::new()))
+            .with_replay(vec!["north".to_string(), "take key".to_string()])
+            .with_recording(&path).unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(repl.repl("west\r\nsouth\n".as_bytes(), &mut out), Ok(AwaitingInput));
+        assert_eq!(String::from_utf8(out).unwrap(), "> north\nnorth\n> take key\ntake key\nwest\nsouth\n");
+        assert_eq!(repl.transcript, ["north", "take key", "west", "south"]);
+
+        // Replay the first three commands without a terminal.
+        let mut commands = read_transcript(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(commands, repl.transcript);
+        commands.truncate(3);
+
+        let mut replay = Repl::new(echo.wrap(AsciiIO::new())).with_replay(commands);
+        assert_eq!(replay.repl(std::io::empty(), std::io::sink()), Ok(AwaitingInput));
+        assert_eq!(replay.system.outer.lines, ["north", "take key", "west"]);
+    }
+}
@@ -0,0 +1,228 @@
+/*
+   Snapshot file format, version 4.  Plain text, one field per line, always in this order:
+
+       intcode-snapshot 4
+       instr_ptr <n>
+       rel_base <n>
+       input <n> | input none
+       arithmetic checked | wrapping | saturating
+       limits <max steps> <max memory> <max outputs>    (each a number or `none`)
+       steps <n>
+       outputs <n>
+       rom <comma-separated words>
+       ram <addr> <comma-separated words>
+
+   There is one `ram` line per allocated run of memory, in increasing address order; anything not
+   covered by a `ram` line is zero.  The rom is kept so that a restored machine can still be reset.
+
+   Older files can still be loaded: version 3 has no `limits`, `steps` or `outputs` lines (there
+   are no limits, and nothing has been counted), version 2 additionally has no `arithmetic` line
+   (it is taken as checked), and version 1 has a single `ram <comma-separated words>` line
+   starting at 0.
+ */
+
+use crate::intcode::cpu::{parse_words, Arithmetic, Limits, CPU};
+use crate::intcode::memory::{Memory, Storage};
+use crate::intcode::word::Word;
+use crate::intcode::IntcodeError::SnapshotFailure;
+use crate::intcode::{IntcodeError, IntcodeResult};
+use itertools::Itertools;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+const MAGIC: &str = "intcode-snapshot";
+const VERSION: isize = 4;
+
+fn failure<E: ToString>(e: E) -> IntcodeError {
+    SnapshotFailure(e.to_string())
+}
+
+fn field<'a>(lines: &mut impl Iterator<Item=&'a str>, key: &str) -> IntcodeResult<&'a str> {
+    let line = lines.next().ok_or(failure(format!("missing `{key}` line")))?;
+    match line.split_once(' ') {
+        Some((k, v)) if k == key => Ok(v),
+        None if line == key => Ok(""),
+        _ => Err(failure(format!("expected `{key}`, found `{line}`")))
+    }
+}
+
+fn number(s: &str) -> IntcodeResult<isize> {
+    s.parse().map_err(|_| failure(format!("bad number `{s}`")))
+}
+
+fn count(s: &str) -> IntcodeResult<usize> {
+    s.parse().map_err(|_| failure(format!("bad count `{s}`")))
+}
+
+fn limit(s: &str) -> IntcodeResult<Option<usize>> {
+    if s == "none" { Ok(None) } else { count(s).map(Some) }
+}
+
+fn words<W: Word>(s: &str) -> IntcodeResult<Vec<W>> {
+    if s.is_empty() { Ok(Vec::new()) } else { parse_words(s) }
+}
+
+impl<W: Word, S: Storage<W>> CPU<W, S> {
+    pub fn save<O: Write>(&self, mut out: O) -> IntcodeResult<()> {
+        let input = self.input.as_ref().map_or("none".to_string(), |v| v.to_string());
+
+        writeln!(out, "{MAGIC} {VERSION}").map_err(failure)?;
+        writeln!(out, "instr_ptr {}", self.instr_ptr).map_err(failure)?;
+        writeln!(out, "rel_base {}", self.rel_base).map_err(failure)?;
+        writeln!(out, "input {input}").map_err(failure)?;
+        writeln!(out, "arithmetic {}", match self.arithmetic {
+            Arithmetic::Checked => "checked",
+            Arithmetic::Wrapping => "wrapping",
+            Arithmetic::Saturating => "saturating"
+        }).map_err(failure)?;
+
+        let Limits { max_steps, max_memory, max_outputs } = self.limits;
+        let limits = [max_steps, max_memory, max_outputs].iter()
+            .map(|l| l.map_or("none".to_string(), |l| l.to_string()))
+            .join(" ");
+        writeln!(out, "limits {limits}").map_err(failure)?;
+        writeln!(out, "steps {}", self.steps).map_err(failure)?;
+        writeln!(out, "outputs {}", self.outputs).map_err(failure)?;
+        writeln!(out, "rom {}", self.memory.rom.iter().join(",")).map_err(failure)?;
+
+        for (addr, words) in self.memory.ram.segments() {
+            let len = words.iter().rposition(|w| !w.is_zero()).map_or(0, |i| i + 1);
+            if len > 0 {
+                writeln!(out, "ram {addr} {}", words[..len].iter().join(",")).map_err(failure)?;
+            }
+        }
+
+        out.flush().map_err(failure)
+    }
+
+    pub fn load<R: BufRead>(input: R) -> IntcodeResult<CPU<W, S>> {
+        let text = input.lines().collect::<Result<Vec<String>, _>>().map_err(failure)?;
+        let mut lines = text.iter().map(|l| l.trim_end()).filter(|l| !l.is_empty()).peekable();
+
+        let version = number(field(&mut lines, MAGIC)?)?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(failure(format!("unsupported snapshot version {version}")));
+        }
+
+        let instr_ptr = number(field(&mut lines, "instr_ptr")?)?;
+        let rel_base = number(field(&mut lines, "rel_base")?)?;
+        let input = match field(&mut lines, "input")? {
+            "none" => None,
+            v => Some(words(v)?.pop().ok_or(failure("missing input value"))?)
+        };
+        let arithmetic = match version {
+            1 | 2 => Arithmetic::Checked,
+            _ => match field(&mut lines, "arithmetic")? {
+                "checked" => Arithmetic::Checked,
+                "wrapping" => Arithmetic::Wrapping,
+                "saturating" => Arithmetic::Saturating,
+                a => return Err(failure(format!("unknown arithmetic `{a}`")))
+            }
+        };
+        let (limits, steps, outputs) = match version {
+            1..=3 => (Limits::default(), 0, 0),
+            _ => {
+                let limits = field(&mut lines, "limits")?.split(' ').map(limit).collect::<IntcodeResult<Vec<_>>>()?;
+                let [max_steps, max_memory, max_outputs] = limits[..] else {
+                    return Err(failure("expected `limits <max steps> <max memory> <max outputs>`"));
+                };
+
+                let limits = Limits { max_steps, max_memory, max_outputs };
+                (limits, count(field(&mut lines, "steps")?)?, count(field(&mut lines, "outputs")?)?)
+            }
+        };
+        let rom = words(field(&mut lines, "rom")?)?;
+
+        let ram = if version == 1 {
+            S::from_words(&words(field(&mut lines, "ram")?)?)
+        }
+        else {
+            let mut ram = S::from_words(&[]);
+            while lines.peek().is_some() {
+                let (addr, segment) = field(&mut lines, "ram")?
+                    .split_once(' ')
+                    .ok_or(failure("expected `ram <addr> <words>`"))?;
+
+                let addr = number(addr)?;
+                if addr < 0 {
+                    return Err(failure(format!("negative ram address {addr}")));
+                }
+
+                for (i, w) in words(segment)?.into_iter().enumerate() {
+                    ram.store(addr as usize + i, w);
+                }
+            }
+            ram
+        };
+
+        if let Some(extra) = lines.next() {
+            return Err(failure(format!("unexpected trailing line `{extra}`")));
+        }
+
+        let memory = Memory::from_parts(rom, ram);
+        Ok(CPU { memory, instr_ptr, rel_base, input, arithmetic, limits, steps, outputs })
+    }
+
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> IntcodeResult<()> {
+        let file = File::create(path).map_err(failure)?;
+        self.save(BufWriter::new(file))
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> IntcodeResult<CPU<W, S>> {
+        let file = File::open(path).map_err(failure)?;
+        CPU::load(BufReader::new(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::memory::Paged;
+    use crate::intcode::IntcodeState::{Halted, OutOfSteps};
+    use crate::intcode::Runnable;
+
+    const QUINE: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+
+    #[test]
+    fn test_round_trip() {
+        let limits = Limits { max_steps: Some(60), ..Limits::default() };
+        let mut cpu = CPU::parse(QUINE).unwrap().with_limits(limits);
+        for _ in 0..40 {
+            cpu.step().unwrap();
+        }
+
+        let mut saved = Vec::new();
+        cpu.save(&mut saved).unwrap();
+
+        let mut restored: CPU = CPU::load(&saved[..]).unwrap();
+        let mut resaved = Vec::new();
+        restored.save(&mut resaved).unwrap();
+        assert_eq!(saved, resaved);
+        assert_eq!((restored.limits, restored.steps, restored.outputs), (limits, 40, cpu.outputs));
+
+        loop {
+            let expected = cpu.step();
+            assert_eq!(restored.step(), expected);
+            assert_eq!((restored.instr_ptr, restored.rel_base), (cpu.instr_ptr, cpu.rel_base));
+            if expected.is_err() || expected == Ok(Halted) || expected == Ok(OutOfSteps) {
+                break;
+            }
+        }
+        assert_eq!((cpu.step(), restored.steps), (Ok(OutOfSteps), 60));
+    }
+
+    #[test]
+    fn test_versions() {
+        let v1 = "intcode-snapshot 1\ninstr_ptr 2\nrel_base 0\ninput none\nrom 4,3,99,7\nram 4,3,4,3,99\n";
+        let mut cpu: CPU = CPU::load(v1.as_bytes()).unwrap();
+        assert_eq!(cpu.run_until_output(), Ok(3));
+
+        let v3 = "intcode-snapshot 3\ninstr_ptr 0\nrel_base 0\ninput none\narithmetic wrapping\nrom 99\nram 0 99\n";
+        let cpu: CPU = CPU::load(v3.as_bytes()).unwrap();
+        assert_eq!((cpu.arithmetic, cpu.limits, cpu.steps), (Arithmetic::Wrapping, Limits::default(), 0));
+
+        let v5 = "intcode-snapshot 5\ninstr_ptr 0\nrel_base 0\ninput none\nrom 99\nram 0 99\n";
+        assert!(matches!(CPU::<isize, Paged>::load(v5.as_bytes()), Err(SnapshotFailure(_))));
+    }
+}
@@ -0,0 +1,315 @@
+use crate::intcode::ascii::AsciiIO;
+use crate::intcode::cpu::CPU;
+use crate::intcode::springscript::{compile, Expr, Mode, Springscript};
+use crate::intcode::IntcodeError::{LogicError, ParsingFailure};
+use crate::intcode::{IntcodeResult, Runnable};
+use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+// How far the springdroid goes when it jumps.
+pub const JUMP: usize = 4;
+
+/*
+   A stretch of hull as the springdroid sees it, true for ground.  The droid starts on the first
+   square, and gets across once it is past the last one; everything past the end is ground.
+ */
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Hull(pub Vec<bool>);
+
+impl Hull {
+    pub fn parse(s: &str) -> IntcodeResult<Hull> {
+        s.trim().chars()
+            .map(|c| match c {
+                '#' | '@' => Ok(true),
+                '.' => Ok(false),
+                _ => Err(ParsingFailure(format!("Bad hull {s:?}")))
+            })
+            .collect::<IntcodeResult<Vec<bool>>>()
+            .map(Hull)
+    }
+
+    // The hull in the first picture of the springdroid's report on how it fell in.
+    pub fn from_report(report: &str) -> IntcodeResult<Hull> {
+        let line = report.lines()
+            .find(|line| line.starts_with('#'))
+            .ok_or(ParsingFailure("No hull in the springdroid's report".to_string()))?;
+
+        Hull::parse(line)
+    }
+
+    fn ground(&self, x: usize) -> bool {
+        self.0.get(x).copied().unwrap_or(true)
+    }
+
+    pub fn sensors(&self, x: usize, mode: Mode) -> Vec<bool> {
+        (1..=mode.sensors()).map(|d| self.ground(x + d)).collect()
+    }
+
+    fn fall<F: FnMut(usize) -> bool>(&self, mut jumps: F) -> Option<usize> {
+        let mut x = 0;
+        while x < self.0.len() {
+            if !self.ground(x) {
+                return Some(x);
+            }
+            x += if jumps(x) { JUMP } else { 1 };
+        }
+
+        None
+    }
+
+    // Where the droid falls in, if it does, jumping whenever the policy says so.
+    pub fn simulate<P: Fn(&[bool]) -> bool>(&self, mode: Mode, policy: P) -> Option<usize> {
+        self.fall(|x| policy(&self.sensors(x, mode)))
+    }
+}
+
+impl Display for Hull {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s: String = self.0.iter().map(|&g| if g { '#' } else { '.' }).collect();
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Report {
+    Damage(isize),
+    Fell(Hull)
+}
+
+// Runs the springscript on the Intcode springdroid.
+pub fn survey(program: &[isize], script: &Springscript) -> IntcodeResult<Report> {
+    let io = AsciiIO::new().with_input(&script.to_string())?;
+    let mut system = CPU::new(program.to_vec()).wrap(io);
+    system.run()?;
+
+    match system.outer.values.last() {
+        Some(&damage) => Ok(Report::Damage(damage)),
+        None => Hull::from_report(&system.outer.text()).map(Report::Fell)
+    }
+}
+
+fn bit(truth: &[u64], k: usize) -> bool {
+    truth[k / 64] >> (k % 64) & 1 == 1
+}
+
+// Candidates refer to their operands by index, which keeps millions of them cheap to hold.
+#[derive(Copy, Clone)]
+enum Node {
+    Literal(char, bool),
+    And(u32, u32),
+    Or(u32, u32)
+}
+
+struct Candidates {
+    nodes: Vec<Node>,
+    truths: Vec<u64>,
+    words: usize,
+    seen: HashSet<Box<[u64]>>
+}
+
+impl Candidates {
+    fn truth(&self, i: usize) -> &[u64] {
+        &self.truths[i * self.words..(i + 1) * self.words]
+    }
+
+    fn expr(&self, node: Node) -> Expr {
+        let operand = |i: u32| Box::new(self.expr(self.nodes[i as usize]));
+
+        match node {
+            Node::Literal(s, true) => Expr::Sensor(s),
+            Node::Literal(s, false) => Expr::Not(Box::new(Expr::Sensor(s))),
+            Node::And(a, b) => Expr::And(operand(a), operand(b)),
+            Node::Or(a, b) => Expr::Or(operand(a), operand(b))
+        }
+    }
+
+    // Whether no earlier candidate decides the same way everywhere; new ones are kept if asked.
+    fn add(&mut self, node: Node, truth: &[u64], keep: bool) -> bool {
+        if self.seen.contains(truth) {
+            return false;
+        }
+        self.seen.insert(truth.into());
+
+        if keep {
+            self.nodes.push(node);
+            self.truths.extend_from_slice(truth);
+        }
+        true
+    }
+}
+
+/*
+   Searches for a jump policy that gets across every hull seen so far.  Candidates are built up
+   from single sensor readings with `&` and `|`, smallest first, and only the first of any that
+   decide the same way at every position on the known hulls is kept.
+ */
+pub struct Synthesizer {
+    pub mode: Mode,
+    pub hulls: Vec<Hull>,
+    pub max_size: usize
+}
+
+impl Synthesizer {
+    pub fn new(mode: Mode) -> Synthesizer {
+        Synthesizer { mode, hulls: Vec::new(), max_size: 6 }
+    }
+
+    pub fn with_max_size(mut self, max_size: usize) -> Synthesizer {
+        self.max_size = max_size;
+        self
+    }
+
+    // The smallest policy, counting sensor readings, that compiles and gets across every hull.
+    pub fn policy(&self) -> IntcodeResult<(Expr, Springscript)> {
+        let mut readings: HashMap<Vec<bool>, usize> = HashMap::new();
+        let positions = self.hulls.iter()
+            .map(|hull| (0..hull.0.len())
+                .map(|x| {
+                    let k = readings.len();
+                    *readings.entry(hull.sensors(x, self.mode)).or_insert(k)
+                })
+                .collect_vec())
+            .collect_vec();
+
+        let survives = |truth: &[u64]| self.hulls.iter().zip(positions.iter())
+            .all(|(hull, pos)| hull.fall(|x| bit(truth, pos[x])).is_none());
+
+        let accept = |candidates: &Candidates, node: Node, truth: &[u64]| {
+            if !survives(truth) {
+                return None;
+            }
+            let expr = candidates.expr(node);
+            compile(&expr, self.mode).ok().map(|script| (expr, script))
+        };
+
+        let words = readings.len() / 64 + 1;
+        let mut candidates = Candidates { nodes: Vec::new(), truths: Vec::new(), words, seen: HashSet::new() };
+        let mut truth = vec![0; words];
+
+        for (i, sensor) in ('A'..='I').take(self.mode.sensors()).enumerate() {
+            for ground in [true, false] {
+                truth.fill(0);
+                for (_, &k) in readings.iter().filter(|(reading, _)| reading[i] == ground) {
+                    truth[k / 64] |= 1 << (k % 64);
+                }
+
+                let node = Node::Literal(sensor, ground);
+                if candidates.add(node, &truth, true) {
+                    if let Some(found) = accept(&candidates, node, &truth) {
+                        return Ok(found);
+                    }
+                }
+            }
+        }
+
+        // The candidates of each size are a range of the kept ones.
+        let mut levels = vec![0..0, 0..candidates.nodes.len()];
+
+        for size in 2..=self.max_size {
+            let start = candidates.nodes.len();
+
+            for left in 1..=size / 2 {
+                let right = size - left;
+                for a in levels[left].clone() {
+                    let from = if left == right { a } else { levels[right].start };
+                    for b in from..levels[right].end {
+                        for and in [true, false] {
+                            let operands = candidates.truth(a).iter().zip(candidates.truth(b));
+                            for (t, (x, y)) in truth.iter_mut().zip(operands) {
+                                *t = if and { x & y } else { x | y };
+                            }
+
+                            let node = if and { Node::And(a as u32, b as u32) } else { Node::Or(a as u32, b as u32) };
+                            if !candidates.add(node, &truth, size < self.max_size) {
+                                continue;
+                            }
+                            if let Some(found) = accept(&candidates, node, &truth) {
+                                return Ok(found);
+                            }
+                        }
+                    }
+                }
+            }
+
+            levels.push(start..candidates.nodes.len());
+        }
+
+        Err(LogicError(format!("No policy of up to {} sensor readings gets across every hull", self.max_size)))
+    }
+
+    // Tries policies on the springdroid, learning each hull it falls into, until one gets across.
+    pub fn run<F>(&mut self, mut attempt: F) -> IntcodeResult<(Expr, isize)>
+    where F: FnMut(&Springscript) -> IntcodeResult<Report> {
+        loop {
+            let (expr, script) = self.policy()?;
+
+            match attempt(&script)? {
+                Report::Damage(damage) => return Ok((expr, damage)),
+                Report::Fell(hull) if self.hulls.contains(&hull) => {
+                    return Err(LogicError(format!("`{expr}` gets across {hull} here, but not on the springdroid")));
+                },
+                Report::Fell(hull) => self.hulls.push(hull)
+            }
+        }
+    }
+
+    pub fn synthesize(&mut self, program: &[isize]) -> IntcodeResult<(Expr, isize)> {
+        self.run(|script| survey(program, script))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::iproduct;
+
+    const REPORT: &str = "\
+Input instructions:
+
+Walking...
+
+
+Didn't make it across:
+
+.................
+.................
+@................
+#####.###########
+
+.................
+.................
+.@...............
+#####.###########
+";
+
+    #[test]
+    fn test_simulate() {
+        let hull = Hull::from_report(REPORT).unwrap();
+        assert_eq!(hull.to_string(), "#####.###########");
+        assert_eq!(hull.simulate(Mode::Walk, |_| false), Some(5));
+        assert_eq!(hull.simulate(Mode::Walk, |s| !s[0] && s[3]), None);
+        assert_eq!(Hull::parse("####.###").unwrap().simulate(Mode::Walk, |_| true), Some(4));
+    }
+
+    #[test]
+    fn test_synthesize() {
+        let hidden = ["#####.###########", "#####...#########", "#####..#.########", "#####.#..########"]
+            .map(|s| Hull::parse(s).unwrap());
+
+        let mut synthesizer = Synthesizer::new(Mode::Walk);
+        let (expr, _) = synthesizer.run(|script| {
+            let fell = hidden.iter().find(|hull| hull.simulate(Mode::Walk, |s| script.execute(s)).is_some());
+            Ok(fell.map_or(Report::Damage(19357), |hull| Report::Fell(hull.clone())))
+        }).unwrap();
+
+        assert!(hidden.iter().all(|hull| hull.simulate(Mode::Walk, |s| expr.eval(s)).is_none()));
+        assert!(synthesizer.hulls.len() <= hidden.len());
+
+        // The compiled springscript decides the same way as the expression.
+        let script = compile(&expr, Mode::Walk).unwrap();
+        for (a, b, c, d) in iproduct!([false, true], [false, true], [false, true], [false, true]) {
+            assert_eq!(script.execute(&[a, b, c, d]), expr.eval(&[a, b, c, d]));
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use crate::intcode::IntcodeError::{LogicError, ParsingFailure};
+use crate::intcode::IntcodeResult;
+use itertools::Itertools;
+use nom::branch::alt;
+use nom::character::complete::{char, multispace0, one_of};
+use nom::combinator::{all_consuming, map};
+use nom::multi::many0;
+use nom::sequence::{delimited, pair, preceded};
+use nom::IResult;
+use std::fmt::{Display, Formatter};
+
+/*
+   Compiles boolean expressions over the springdroid's sensors into springscript, e.g.
+
+       !(A & B & C) & D
+
+   `!` (or `~`) binds tightest, then `&`, then `|`.  The result ends up in J; T is the only other
+   register, so an expression can only be compiled if at most one operand of each `&` or `|` needs
+   both registers to compute.  Sensors are A to D when walking and A to I when running.
+ */
+
+pub const MAX_INSTRUCTIONS: usize = 15;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Expr {
+    Sensor(char),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Mode {
+    Walk,
+    Run
+}
+
+impl Mode {
+    pub fn sensors(&self) -> usize {
+        match self {
+            Mode::Walk => 4,
+            Mode::Run => 9
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Op {
+    And,
+    Or,
+    Not
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Instr(pub Op, pub char, pub char);
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Springscript {
+    pub instrs: Vec<Instr>,
+    pub mode: Mode
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let operand = |e: &Expr| match e {
+            Expr::Or(_, _) => format!("({e})"),
+            _ => e.to_string()
+        };
+
+        match self {
+            Expr::Sensor(s) => write!(f, "{s}"),
+            Expr::Not(e) => match **e {
+                Expr::Sensor(s) => write!(f, "!{s}"),
+                _ => write!(f, "!({e})")
+            },
+            Expr::And(a, b) => write!(f, "{} & {}", operand(a), operand(b)),
+            Expr::Or(a, b) => write!(f, "{a} | {b}")
+        }
+    }
+}
+
+impl Display for Instr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Instr(op, x, y) = self;
+        let op = match op {
+            Op::And => "AND",
+            Op::Or => "OR",
+            Op::Not => "NOT"
+        };
+        write!(f, "{op} {x} {y}")
+    }
+}
+
+// The program as the springdroid reads it, one instruction per line.
+impl Display for Springscript {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for instr in self.instrs.iter() {
+            writeln!(f, "{instr}")?;
+        }
+
+        match self.mode {
+            Mode::Walk => writeln!(f, "WALK"),
+            Mode::Run => writeln!(f, "RUN")
+        }
+    }
+}
+
+fn atom(input: &str) -> IResult<&str, Expr> {
+    delimited(
+        multispace0,
+        alt((
+            map(one_of("ABCDEFGHI"), Expr::Sensor),
+            delimited(char('('), disjunction, char(')')),
+            map(preceded(one_of("!~"), atom), |e| Expr::Not(Box::new(e)))
+        )),
+        multispace0
+    )(input)
+}
+
+fn conjunction(input: &str) -> IResult<&str, Expr> {
+    map(pair(atom, many0(preceded(char('&'), atom))), |(first, rest)| {
+        rest.into_iter().fold(first, |a, b| Expr::And(Box::new(a), Box::new(b)))
+    })(input)
+}
+
+fn disjunction(input: &str) -> IResult<&str, Expr> {
+    map(pair(conjunction, many0(preceded(char('|'), conjunction))), |(first, rest)| {
+        rest.into_iter().fold(first, |a, b| Expr::Or(Box::new(a), Box::new(b)))
+    })(input)
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> IntcodeResult<Expr> {
+        all_consuming(disjunction)(input)
+            .map(|(_, expr)| expr)
+            .map_err(|e| ParsingFailure(format!("Bad springscript expression {input:?}: {e}")))
+    }
+
+    // Sensor readings are in order from A; true means ground.
+    pub fn eval(&self, sensors: &[bool]) -> bool {
+        match self {
+            Expr::Sensor(s) => sensors[sensor_index(*s)],
+            Expr::Not(e) => !e.eval(sensors),
+            Expr::And(a, b) => a.eval(sensors) && b.eval(sensors),
+            Expr::Or(a, b) => a.eval(sensors) || b.eval(sensors)
+        }
+    }
+
+    pub fn sensors(&self) -> Vec<char> {
+        match self {
+            Expr::Sensor(s) => vec![*s],
+            Expr::Not(e) => e.sensors(),
+            Expr::And(a, b) | Expr::Or(a, b) => a.sensors().into_iter().chain(b.sensors()).collect()
+        }
+    }
+
+    // Pushes every negation down onto a sensor.
+    pub fn normalized(&self) -> Expr {
+        self.normalize(false)
+    }
+
+    fn normalize(&self, negate: bool) -> Expr {
+        let both = |a: &Expr, b: &Expr| (Box::new(a.normalize(negate)), Box::new(b.normalize(negate)));
+
+        match (self, negate) {
+            (Expr::Sensor(_), false) => self.clone(),
+            (Expr::Sensor(_), true) => Expr::Not(Box::new(self.clone())),
+            (Expr::Not(e), _) => e.normalize(!negate),
+            (Expr::And(a, b), false) | (Expr::Or(a, b), true) => {
+                let (a, b) = both(a, b);
+                Expr::And(a, b)
+            },
+            (Expr::Or(a, b), false) | (Expr::And(a, b), true) => {
+                let (a, b) = both(a, b);
+                Expr::Or(a, b)
+            }
+        }
+    }
+
+    fn op(&self) -> Option<Op> {
+        match self {
+            Expr::And(_, _) => Some(Op::And),
+            Expr::Or(_, _) => Some(Op::Or),
+            _ => None
+        }
+    }
+
+    // The operands of a chain of the same operator, e.g. a, b and c for a & (b & c).
+    fn operands(&self) -> Vec<&Expr> {
+        match self {
+            Expr::And(a, b) | Expr::Or(a, b) => [a, b].into_iter()
+                .flat_map(|e| if e.op() == self.op() { e.operands() } else { vec![&**e] })
+                .collect(),
+            _ => vec![self]
+        }
+    }
+
+    // Whether computing this into one register needs the other one as well.
+    fn needs_scratch(&self) -> bool {
+        match self {
+            Expr::Sensor(_) => false,
+            Expr::Not(e) => e.needs_scratch(),
+            _ => {
+                let computed = self.operands().into_iter()
+                    .filter(|e| !matches!(e, Expr::Sensor(_)))
+                    .collect_vec();
+
+                computed.len() > 1 || computed.iter().any(|e| e.needs_scratch())
+            }
+        }
+    }
+}
+
+impl Springscript {
+    // Whether the springdroid jumps, given what its sensors read.
+    pub fn execute(&self, sensors: &[bool]) -> bool {
+        let (mut t, mut j) = (false, false);
+
+        for &Instr(op, x, y) in self.instrs.iter() {
+            let x = match x {
+                'T' => t,
+                'J' => j,
+                s => sensors[sensor_index(s)]
+            };
+            let y = if y == 'T' { &mut t } else { &mut j };
+
+            *y = match op {
+                Op::And => x && *y,
+                Op::Or => x || *y,
+                Op::Not => !x
+            };
+        }
+
+        j
+    }
+}
+
+fn sensor_index(sensor: char) -> usize {
+    (sensor as u8 - b'A') as usize
+}
+
+struct Codegen {
+    instrs: Vec<Instr>,
+    // Registers that haven't been written yet, so still hold false.
+    clean: Vec<char>
+}
+
+fn other(reg: char) -> char {
+    if reg == 'J' { 'T' } else { 'J' }
+}
+
+impl Codegen {
+    fn emit(&mut self, op: Op, x: char, y: char) {
+        self.clean.retain(|&r| r != y);
+        self.instrs.push(Instr(op, x, y));
+    }
+
+    fn gen(&mut self, expr: &Expr, dst: char) -> Result<(), ()> {
+        match expr {
+            Expr::Sensor(s) if self.clean.contains(&dst) => self.emit(Op::Or, *s, dst),
+            Expr::Sensor(s) => {
+                self.emit(Op::Not, *s, dst);
+                self.emit(Op::Not, dst, dst);
+            },
+            Expr::Not(e) => match **e {
+                Expr::Sensor(s) => self.emit(Op::Not, s, dst),
+                _ => {
+                    self.gen(e, dst)?;
+                    self.emit(Op::Not, dst, dst);
+                }
+            },
+            Expr::And(_, _) | Expr::Or(_, _) => {
+                let op = expr.op().unwrap();
+                let (sensors, mut computed): (Vec<&Expr>, Vec<&Expr>) = expr.operands().into_iter()
+                    .partition(|e| matches!(e, Expr::Sensor(_)));
+
+                // Only the first computed operand may use the other register.
+                computed.sort_by_key(|e| !e.needs_scratch());
+                if computed.iter().skip(1).any(|e| e.needs_scratch()) {
+                    return Err(());
+                }
+
+                let mut sensors = sensors.into_iter();
+                match computed.first() {
+                    Some(first) => self.gen(first, dst)?,
+                    None => self.gen(sensors.next().unwrap(), dst)?
+                }
+
+                for e in computed.iter().skip(1) {
+                    self.gen(e, other(dst))?;
+                    self.emit(op, other(dst), dst);
+                }
+                for e in sensors {
+                    if let Expr::Sensor(s) = e {
+                        self.emit(op, *s, dst);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn generate(expr: &Expr) -> Option<Vec<Instr>> {
+    let mut codegen = Codegen { instrs: Vec::new(), clean: vec!['T', 'J'] };
+    codegen.gen(expr, 'J').ok().map(|_| codegen.instrs)
+}
+
+// Compiles the expression both as written and with its negations pushed down, keeping the shorter.
+pub fn compile(expr: &Expr, mode: Mode) -> IntcodeResult<Springscript> {
+    let sensors = ('A'..='I').take(mode.sensors()).collect_vec();
+    if let Some(s) = expr.sensors().into_iter().find(|s| !sensors.contains(s)) {
+        return Err(LogicError(format!("Sensor {s} isn't available in {mode:?} mode")));
+    }
+
+    let instrs = [expr.clone(), expr.normalized()].iter()
+        .filter_map(generate)
+        .min_by_key(|instrs| instrs.len())
+        .ok_or(LogicError(format!("`{expr}` needs more registers than T and J")))?;
+
+    if instrs.len() > MAX_INSTRUCTIONS {
+        let n = instrs.len();
+        return Err(LogicError(format!("`{expr}` needs {n} instructions, but springscript allows {MAX_INSTRUCTIONS}")));
+    }
+
+    Ok(Springscript { instrs, mode })
+}
+
+pub fn compile_str(expr: &str, mode: Mode) -> IntcodeResult<Springscript> {
+    compile(&Expr::parse(expr)?, mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile() {
+        let walk = compile_str("!(A & B & C) & D", Mode::Walk).unwrap();
+        assert_eq!(walk.to_string(), "OR A J\nAND B J\nAND C J\nNOT J J\nAND D J\nWALK\n");
+
+        let run = compile_str("(H & D & (!B | !C)) | !A", Mode::Run).unwrap();
+        assert_eq!(run.to_string(), "NOT B J\nNOT C T\nOR T J\nAND H J\nAND D J\nNOT A T\nOR T J\nRUN\n");
+
+        let expr = Expr::parse("!((A | B) & !C)").unwrap();
+        assert_eq!(expr.to_string(), "!((A | B) & !C)");
+        assert_eq!(expr.normalized().to_string(), "!A & !B | C");
+        assert_eq!(Expr::parse("~(A | ~B)"), Expr::parse("!(A | !B)"));
+
+        assert!(matches!(compile_str("A & E", Mode::Walk), Err(LogicError(_))));
+        assert!(matches!(compile_str("A & (B |", Mode::Walk), Err(ParsingFailure(_))));
+        assert!(matches!(compile_str("(A & B | C & D) & (E & F | G & H)", Mode::Run), Err(LogicError(_))));
+        assert!(matches!(compile_str("!A & !B & !C & !D & !E & !F & !G & !H & !I", Mode::Run), Err(LogicError(_))));
+    }
+}
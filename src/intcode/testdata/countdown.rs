@@ -0,0 +1,171 @@
+// Generated by intcode::transpile; do not edit.
+
+use crate::intcode::cpu::CPU;
+use crate::intcode::memory::Storage;
+use crate::intcode::word::Word;
+use crate::intcode::IntcodeError::{MemoryLimit, OutputLimit, Overflow};
+use crate::intcode::IntcodeState::{AwaitingInput, Continue, Halted, OutOfSteps, OutputGenerated};
+use crate::intcode::{IntcodeResult, IntcodeState, Resettable, Runnable};
+
+const PROGRAM: [isize; 27] = [
+    3, 26, 109, 100, 4, 26, 20201, 0, 26, 0, 1001, 26, -1, 26, 1005, 26,
+    4, 204, 0, 1101, 0, 99, 23, 104, 7, 99, 0,
+];
+
+#[derive(Clone)]
+pub struct Countdown {
+    pub cpu: CPU,
+    interpreted: bool
+}
+
+impl Default for Countdown {
+    fn default() -> Countdown {
+        Countdown::new()
+    }
+}
+
+impl Countdown {
+    pub fn new() -> Countdown {
+        Countdown { cpu: CPU::new(PROGRAM.to_vec()), interpreted: false }
+    }
+
+    pub fn interpreted(&self) -> bool {
+        self.interpreted
+    }
+
+    fn is_code(addr: isize) -> bool {
+        matches!(addr, 0..=25)
+    }
+
+    fn fallback(&mut self) -> IntcodeResult<IntcodeState<isize>> {
+        let target = self.cpu.cur_instr().ok()
+            .and_then(|instr| instr.target())
+            .and_then(|param| self.cpu.address(param));
+
+        let state = self.cpu.step()?;
+        if matches!(state, Continue) && target.is_some_and(Self::is_code) {
+            self.interpreted = true;
+        }
+        Ok(state)
+    }
+
+    fn relative(&self, offset: isize) -> IntcodeResult<isize> {
+        self.cpu.rel_base.checked_add(offset).ok_or(Overflow(self.cpu.instr_ptr))
+    }
+
+    fn store(&mut self, addr: isize, value: isize) -> IntcodeResult<()> {
+        let allocated = usize::try_from(addr).map(|a| self.cpu.memory.ram().allocated_with(a));
+        if let Some(max) = self.cpu.limits.max_memory.filter(|&max| allocated.is_ok_and(|n| n > max)) {
+            return Err(MemoryLimit(max));
+        }
+
+        if Self::is_code(addr) {
+            self.interpreted = true;
+        }
+        self.cpu.memory.set(addr, value)
+    }
+}
+
+impl Runnable for Countdown {
+    type Input = isize;
+    type Output = isize;
+
+    fn accept_input(&mut self, input: isize) -> IntcodeResult<()> {
+        self.cpu.accept_input(input)
+    }
+
+    fn step(&mut self) -> IntcodeResult<IntcodeState<isize>> {
+        if self.interpreted {
+            return self.cpu.step();
+        }
+
+        if self.cpu.limits.max_steps.is_some_and(|max| self.cpu.steps >= max) {
+            return Ok(OutOfSteps);
+        }
+
+        let state = match self.cpu.instr_ptr {
+            0 => {
+                let Some(v) = self.cpu.input.take() else { return Ok(AwaitingInput) };
+                self.store(26, v)?;
+                self.cpu.instr_ptr = 2;
+                Ok(Continue)
+            },
+            2 => {
+                let a: isize = 100;
+                self.cpu.rel_base = self.cpu.rel_base.add_with(&a, self.cpu.arithmetic).ok_or(Overflow(2))?;
+                self.cpu.instr_ptr = 4;
+                Ok(Continue)
+            },
+            4 => {
+                if let Some(max) = self.cpu.limits.max_outputs.filter(|&max| self.cpu.outputs >= max) {
+                    return Err(OutputLimit(max));
+                }
+                let a: isize = self.cpu.memory.get(26)?;
+                self.cpu.outputs += 1;
+                self.cpu.instr_ptr = 6;
+                Ok(OutputGenerated(a))
+            },
+            6 => {
+                let a: isize = self.cpu.memory.get(self.relative(0)?)?;
+                let b: isize = self.cpu.memory.get(26)?;
+                let r = a.add_with(&b, self.cpu.arithmetic).ok_or(Overflow(6))?;
+                self.store(self.relative(0)?, r)?;
+                self.cpu.instr_ptr = 10;
+                Ok(Continue)
+            },
+            10 => {
+                let a: isize = self.cpu.memory.get(26)?;
+                let b: isize = -1;
+                let r = a.add_with(&b, self.cpu.arithmetic).ok_or(Overflow(10))?;
+                self.store(26, r)?;
+                self.cpu.instr_ptr = 14;
+                Ok(Continue)
+            },
+            14 => {
+                let a: isize = self.cpu.memory.get(26)?;
+                self.cpu.instr_ptr = if a != 0 { 4 } else { 17 };
+                Ok(Continue)
+            },
+            17 => {
+                if let Some(max) = self.cpu.limits.max_outputs.filter(|&max| self.cpu.outputs >= max) {
+                    return Err(OutputLimit(max));
+                }
+                let a: isize = self.cpu.memory.get(self.relative(0)?)?;
+                self.cpu.outputs += 1;
+                self.cpu.instr_ptr = 19;
+                Ok(OutputGenerated(a))
+            },
+            19 => {
+                let a: isize = 0;
+                let b: isize = 99;
+                let r = a.add_with(&b, self.cpu.arithmetic).ok_or(Overflow(19))?;
+                self.store(23, r)?;
+                self.cpu.instr_ptr = 23;
+                Ok(Continue)
+            },
+            23 => {
+                if let Some(max) = self.cpu.limits.max_outputs.filter(|&max| self.cpu.outputs >= max) {
+                    return Err(OutputLimit(max));
+                }
+                let a: isize = 7;
+                self.cpu.outputs += 1;
+                self.cpu.instr_ptr = 25;
+                Ok(OutputGenerated(a))
+            },
+            25 => {
+                Ok(Halted)
+            },
+            _ => return self.fallback()
+        }?;
+
+        self.cpu.steps += 1;
+        Ok(state)
+    }
+}
+
+impl Resettable for Countdown {
+    fn reset(&mut self) {
+        self.cpu.reset();
+        self.interpreted = false;
+    }
+}
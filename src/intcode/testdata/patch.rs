@@ -0,0 +1,98 @@
+// Generated by intcode::transpile; do not edit.
+
+use crate::intcode::cpu::CPU;
+use crate::intcode::IntcodeError::OutputLimit;
+use crate::intcode::IntcodeState::{Continue, Halted, OutOfSteps, OutputGenerated};
+use crate::intcode::{IntcodeResult, IntcodeState, Resettable, Runnable};
+
+const PROGRAM: [isize; 14] = [
+    105, 1, 13, 104, 5, 99, 1101, 0, 9, 4, 1105, 1, 3, 6,
+];
+
+#[derive(Clone)]
+pub struct Patch {
+    pub cpu: CPU,
+    interpreted: bool
+}
+
+impl Default for Patch {
+    fn default() -> Patch {
+        Patch::new()
+    }
+}
+
+impl Patch {
+    pub fn new() -> Patch {
+        Patch { cpu: CPU::new(PROGRAM.to_vec()), interpreted: false }
+    }
+
+    pub fn interpreted(&self) -> bool {
+        self.interpreted
+    }
+
+    fn is_code(addr: isize) -> bool {
+        matches!(addr, 0..=5)
+    }
+
+    fn fallback(&mut self) -> IntcodeResult<IntcodeState<isize>> {
+        let target = self.cpu.cur_instr().ok()
+            .and_then(|instr| instr.target())
+            .and_then(|param| self.cpu.address(param));
+
+        let state = self.cpu.step()?;
+        if matches!(state, Continue) && target.is_some_and(Self::is_code) {
+            self.interpreted = true;
+        }
+        Ok(state)
+    }
+}
+
+impl Runnable for Patch {
+    type Input = isize;
+    type Output = isize;
+
+    fn accept_input(&mut self, input: isize) -> IntcodeResult<()> {
+        self.cpu.accept_input(input)
+    }
+
+    fn step(&mut self) -> IntcodeResult<IntcodeState<isize>> {
+        if self.interpreted {
+            return self.cpu.step();
+        }
+
+        if self.cpu.limits.max_steps.is_some_and(|max| self.cpu.steps >= max) {
+            return Ok(OutOfSteps);
+        }
+
+        let state = match self.cpu.instr_ptr {
+            0 => {
+                let a: isize = 1;
+                self.cpu.instr_ptr = if a != 0 { self.cpu.memory.get(13)? } else { 3 };
+                Ok(Continue)
+            },
+            3 => {
+                if let Some(max) = self.cpu.limits.max_outputs.filter(|&max| self.cpu.outputs >= max) {
+                    return Err(OutputLimit(max));
+                }
+                let a: isize = 5;
+                self.cpu.outputs += 1;
+                self.cpu.instr_ptr = 5;
+                Ok(OutputGenerated(a))
+            },
+            5 => {
+                Ok(Halted)
+            },
+            _ => return self.fallback()
+        }?;
+
+        self.cpu.steps += 1;
+        Ok(state)
+    }
+}
+
+impl Resettable for Patch {
+    fn reset(&mut self) {
+        self.cpu.reset();
+        self.interpreted = false;
+    }
+}
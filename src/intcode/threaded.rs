@@ -0,0 +1,307 @@
+use crate::intcode::io::{IProvider, OProvider};
+use crate::intcode::IntcodeError::LogicError;
+use crate::intcode::IntcodeState::{AwaitingInput, Continue, Halted, OutputGenerated};
+use crate::intcode::{IntcodeResult, IntcodeState, Runnable};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+// How often a machine blocked on an empty channel checks whether the run is over.
+const TICK: Duration = Duration::from_millis(5);
+
+/*
+   Book-keeping shared by every machine and channel in a run.  The run is quiescent, and stops,
+   once no machine is running and every machine waiting for input is waiting on a channel with
+   nothing in it.  Sends and receives update the counts under the lock, so a value sent to a
+   waiting machine always keeps the run alive until that machine picks it up.
+ */
+#[derive(Default)]
+struct Activity {
+    running: usize,
+    pending: Vec<usize>,
+    blocked: Vec<bool>
+}
+
+impl Activity {
+    fn quiescent(&self) -> bool {
+        self.running == 0 && self.pending.iter().zip(self.blocked.iter()).all(|(&n, &b)| !b || n == 0)
+    }
+}
+
+#[derive(Clone, Default)]
+struct Monitor {
+    activity: Arc<Mutex<Activity>>,
+    stopped: Arc<AtomicBool>
+}
+
+impl Monitor {
+    fn lock(&self) -> MutexGuard<'_, Activity> {
+        self.activity.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn stop_if_quiescent(&self, activity: &Activity) {
+        if activity.quiescent() {
+            self.stopped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+}
+
+pub struct ChannelInput<T> {
+    id: usize,
+    rx: Receiver<T>,
+    tx: Sender<T>,
+    monitor: Monitor
+}
+
+#[derive(Clone)]
+pub struct ChannelOutput<T> {
+    id: usize,
+    tx: Sender<T>,
+    monitor: Monitor
+}
+
+impl<T> ChannelOutput<T> {
+    pub fn send(&self, value: T) -> IntcodeResult<()> {
+        let mut activity = self.monitor.lock();
+        self.tx.send(value).map_err(|_| LogicError("Channel receiver is gone".to_string()))?;
+        activity.pending[self.id] += 1;
+        Ok(())
+    }
+}
+
+impl<T> ChannelInput<T> {
+    pub fn sender(&self) -> ChannelOutput<T> {
+        ChannelOutput { id: self.id, tx: self.tx.clone(), monitor: self.monitor.clone() }
+    }
+
+    // Takes everything left in the channel, e.g. the outputs of a machine that has halted.
+    pub fn drain(&mut self) -> Vec<T> {
+        let mut activity = self.monitor.lock();
+        let values: Vec<T> = self.rx.try_iter().collect();
+        activity.pending[self.id] -= values.len();
+        values
+    }
+}
+
+impl<T> IProvider for ChannelInput<T> {
+    type PInput = T;
+    type RInput = T;
+
+    fn provide_input<O>(&mut self) -> IntcodeResult<(IntcodeState<O>, Option<T>)> {
+        {
+            let mut activity = self.monitor.lock();
+            match self.rx.try_recv() {
+                Ok(value) => {
+                    activity.pending[self.id] -= 1;
+                    return Ok((Continue, Some(value)));
+                },
+                Err(_) => {
+                    activity.running -= 1;
+                    activity.blocked[self.id] = true;
+                    self.monitor.stop_if_quiescent(&activity);
+                }
+            }
+        }
+
+        loop {
+            if self.monitor.stopped() {
+                return Ok((AwaitingInput, None));
+            }
+
+            match self.rx.recv_timeout(TICK) {
+                Ok(value) => {
+                    let mut activity = self.monitor.lock();
+                    activity.pending[self.id] -= 1;
+                    activity.blocked[self.id] = false;
+                    activity.running += 1;
+                    return Ok((Continue, Some(value)));
+                },
+                Err(_) => continue
+            }
+        }
+    }
+
+    fn receive_input(&mut self, input: T) -> IntcodeResult<()> {
+        self.sender().send(input)
+    }
+}
+
+impl<T: Clone> OProvider for ChannelOutput<T> {
+    type POutput = T;
+    type ROutput = T;
+
+    fn handle_output(&mut self, output: T) -> IntcodeResult<IntcodeState<T>> {
+        self.send(output.clone())?;
+        Ok(OutputGenerated(output))
+    }
+}
+
+/*
+   Runs machines on one thread each until every one of them has halted or the run is quiescent.
+   Machines should take their input from channels made by the same runner; a machine waiting on
+   anything else counts as running and keeps the run going.
+ */
+#[derive(Default)]
+pub struct Runner {
+    monitor: Monitor
+}
+
+impl Runner {
+    pub fn new() -> Runner {
+        Runner::default()
+    }
+
+    pub fn channel<T>(&self) -> (ChannelOutput<T>, ChannelInput<T>) {
+        let (tx, rx) = mpsc::channel();
+
+        let mut activity = self.monitor.lock();
+        let id = activity.pending.len();
+        activity.pending.push(0);
+        activity.blocked.push(false);
+
+        let input = ChannelInput { id, rx, tx, monitor: self.monitor.clone() };
+        (input.sender(), input)
+    }
+
+    // Returns the machines once they have all stopped, or the first error any of them hit; a
+    // machine thread panicking stops the run with an error too.
+    pub fn run<R: Runnable + Send>(&self, mut machines: Vec<R>) -> IntcodeResult<Vec<R>> {
+        self.monitor.lock().running = machines.len();
+        self.monitor.stopped.store(false, Ordering::SeqCst);
+
+        let results: Vec<IntcodeResult<()>> = thread::scope(|scope| {
+            let handles: Vec<_> = machines.iter_mut()
+                .map(|machine| scope.spawn(|| self.drive(machine)))
+                .collect();
+
+            let panicked = || Err(LogicError("Machine thread panicked".to_string()));
+            handles.into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|_| panicked()))
+                .collect()
+        });
+
+        results.into_iter().collect::<IntcodeResult<Vec<()>>>()?;
+        Ok(machines)
+    }
+
+    fn drive<R: Runnable>(&self, machine: &mut R) -> IntcodeResult<()> {
+        let _guard = PanicGuard(&self.monitor);
+
+        let result = loop {
+            if self.monitor.stopped() {
+                break Ok(());
+            }
+
+            match machine.step() {
+                Ok(Halted) => break Ok(()),
+                Ok(_) => (),
+                Err(e) => break Err(e)
+            }
+        };
+
+        let mut activity = self.monitor.lock();
+        if result.is_err() {
+            self.monitor.stopped.store(true, Ordering::SeqCst);
+        }
+        else if !self.monitor.stopped() {
+            activity.running -= 1;
+            self.monitor.stop_if_quiescent(&activity);
+        }
+
+        result
+    }
+}
+
+// Stops the run if a machine's thread panics, so the others don't wait for it forever.
+struct PanicGuard<'a>(&'a Monitor);
+
+impl Drop for PanicGuard<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            let mut activity = self.0.lock();
+            activity.running = activity.running.saturating_sub(1);
+            self.0.stopped.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::cpu::CPU;
+    use crate::intcode::io::Bus;
+    use itertools::Itertools;
+
+    #[test]
+    fn test_feedback_loop() {
+        let code = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+        let runner = Runner::new();
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..5).map(|_| runner.channel()).unzip();
+
+        let mut machines = receivers.into_iter().zip([9, 8, 7, 6, 5]).enumerate()
+            .map(|(i, (input, phase))| {
+                let output = senders[(i + 1) % 5].clone();
+                let mut machine = CPU::parse(code).unwrap().wrap(Bus { input, output });
+                machine.accept_input(phase).unwrap();
+                machine
+            })
+            .collect_vec();
+
+        machines[0].accept_input(0).unwrap();
+        let mut machines = runner.run(machines).unwrap();
+        assert_eq!(machines[0].outer.input.drain(), vec![139629729]);
+    }
+
+    #[test]
+    fn test_quiescence() {
+        // Two machines each waiting for the other to say something first.
+        let runner = Runner::new();
+        let (a_out, a_in) = runner.channel();
+        let (b_out, b_in) = runner.channel();
+
+        let a = CPU::parse("3,0,4,0,99").unwrap().wrap(Bus { input: a_in, output: b_out });
+        let b = CPU::parse("3,0,4,0,99").unwrap().wrap(Bus { input: b_in, output: a_out });
+
+        let machines = runner.run(vec![a, b]).unwrap();
+        assert!(machines.iter().all(|m| m.inner.instr_ptr == 0));
+    }
+
+    // Panics where the machine it wraps would output 13.
+    struct Unlucky<R>(R);
+
+    impl<R: Runnable<Output=isize>> Runnable for Unlucky<R> {
+        type Input = R::Input;
+        type Output = isize;
+
+        fn accept_input(&mut self, input: R::Input) -> IntcodeResult<()> {
+            self.0.accept_input(input)
+        }
+
+        fn step(&mut self) -> IntcodeResult<IntcodeState<isize>> {
+            match self.0.step()? {
+                OutputGenerated(13) => panic!("Unlucky output"),
+                state => Ok(state)
+            }
+        }
+    }
+
+    #[test]
+    fn test_panic() {
+        // A reads forever; B sends it one value and panics.
+        let runner = Runner::new();
+        let (a_out, a_in) = runner.channel();
+        let (b_out, b_in) = runner.channel();
+
+        let a = CPU::parse("3,5,1105,1,0,0").unwrap().wrap(Bus { input: a_in, output: b_out });
+        let b = CPU::parse("104,13,99").unwrap().wrap(Bus { input: b_in, output: a_out });
+
+        let result = runner.run(vec![Unlucky(a), Unlucky(b)]);
+        assert_eq!(result.err(), Some(LogicError("Machine thread panicked".to_string())));
+    }
+}
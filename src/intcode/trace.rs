@@ -0,0 +1,215 @@
+/*
+   Trace file format, version 1.
+
+   The first line is the header `# intcode-trace v1`.  Every step of the CPU then produces one
+   line of tab-separated fields:
+
+       <step>  <addr>  <instruction>  <events>
+
+   <step> counts from 0, <addr> is the instruction pointer before the step and <instruction> is
+   the instruction as printed by the disassembler (`.data n` if it doesn't decode).  <events> is
+   a space-separated list, in this order, of:
+
+       args=a,b,...   values read for the instruction's source operands
+       in=v           input value consumed
+       w[addr]=v      memory write
+       rb=n           new relative base
+       out=v          output produced
+       wait           no input was available; the instruction will be retried
+       halt           the program halted
+       limit          the CPU's step limit was reached; nothing was executed
+       error=e        the step failed with error e (Debug format)
+
+   A reset of the traced CPU is recorded as the line `# reset`, after which steps count from 0.
+ */
+
+use crate::intcode::cpu::{Instruction, Parameter, CPU};
+use crate::intcode::disasm::disassemble_at;
+use crate::intcode::memory::{Paged, Storage};
+use crate::intcode::word::Word;
+use crate::intcode::IntcodeError::LogicError;
+use crate::intcode::IntcodeState::{AwaitingInput, Halted, OutOfSteps, OutputGenerated};
+use crate::intcode::{IntcodeError, IntcodeResult, IntcodeState, Resettable, Runnable};
+use itertools::Itertools;
+use std::io::Write;
+
+pub const HEADER: &str = "# intcode-trace v1";
+
+pub struct Traced<O: Write, W: Word = isize, S: Storage<W> = Paged<W>> {
+    pub cpu: CPU<W, S>,
+    out: O,
+    step: usize,
+    started: bool
+}
+
+impl<O: Write, W: Word, S: Storage<W>> Traced<O, W, S> {
+    pub fn new(cpu: CPU<W, S>, out: O) -> Traced<O, W, S> {
+        Traced { cpu, out, step: 0, started: false }
+    }
+
+    pub fn into_inner(self) -> (CPU<W, S>, O) {
+        (self.cpu, self.out)
+    }
+
+    fn write_line(&mut self, line: &str) -> IntcodeResult<()> {
+        if !self.started {
+            self.started = true;
+            writeln!(self.out, "{HEADER}").map_err(write_failure)?;
+        }
+        writeln!(self.out, "{line}").map_err(write_failure)
+    }
+}
+
+impl<W: Word, S: Storage<W>> CPU<W, S> {
+    pub fn traced<O: Write>(self, out: O) -> Traced<O, W, S> {
+        Traced::new(self, out)
+    }
+}
+
+fn write_failure(e: std::io::Error) -> IntcodeError {
+    LogicError(format!("Failed to write trace: {e}"))
+}
+
+fn sources<W: Clone>(instr: &Instruction<W>) -> Vec<Parameter<W>> {
+    let mut params = instr.params();
+    if instr.target().is_some() {
+        params.pop();
+    }
+    params
+}
+
+impl<O: Write, W: Word, S: Storage<W>> Runnable for Traced<O, W, S> {
+    type Input = W;
+    type Output = W;
+
+    fn accept_input(&mut self, input: W) -> IntcodeResult<()> {
+        self.cpu.accept_input(input)
+    }
+
+    fn step(&mut self) -> IntcodeResult<IntcodeState<W>> {
+        let ip = self.cpu.instr_ptr;
+        let rb = self.cpu.rel_base;
+        let pending = self.cpu.input.clone();
+        let instr = self.cpu.cur_instr().ok();
+        let text = disassemble_at(&self.cpu.memory, ip);
+
+        let mut events = Vec::new();
+
+        if let Some(instr) = &instr {
+            let args = sources(instr).into_iter()
+                .filter_map(|p| self.cpu.get(p).ok())
+                .join(",");
+
+            if !args.is_empty() {
+                events.push(format!("args={args}"));
+            }
+        }
+
+        let target = instr.as_ref().and_then(|i| i.target()).and_then(|p| self.cpu.address(p));
+        let result = self.cpu.step();
+        let executed = self.cpu.instr_ptr != ip;
+
+        if let (Some(Instruction::Input(_)), Some(v), true) = (instr, pending, executed) {
+            events.push(format!("in={v}"));
+        }
+
+        if let (Some(addr), true) = (target, executed) {
+            if let Ok(v) = self.cpu.memory.get(addr) {
+                events.push(format!("w[{addr}]={v}"));
+            }
+        }
+
+        if self.cpu.rel_base != rb {
+            events.push(format!("rb={}", self.cpu.rel_base));
+        }
+
+        match &result {
+            Ok(OutputGenerated(v)) => events.push(format!("out={v}")),
+            Ok(AwaitingInput) => events.push("wait".to_string()),
+            Ok(Halted) => events.push("halt".to_string()),
+            Ok(OutOfSteps) => events.push("limit".to_string()),
+            Err(e) => events.push(format!("error={e:?}")),
+            _ => ()
+        }
+
+        let line = format!("{}\t{ip}\t{text}\t{}", self.step, events.join(" "));
+        self.step += 1;
+        self.write_line(&line)?;
+
+        result
+    }
+}
+
+impl<O: Write, W: Word, S: Storage<W>> Resettable for Traced<O, W, S> {
+    fn reset(&mut self) {
+        self.cpu.reset();
+        self.step = 0;
+        if self.started {
+            let _ = writeln!(self.out, "# reset");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+
+    const PROGRAM: &str = "
+            IN [x]
+            ARB #x
+            ADD [x], rb+1, rb+0
+            OUT rb+0
+            IN [x]
+            HLT
+    x:      .data 0, 3
+    ";
+
+    #[test]
+    fn test_format() {
+        let cpu = CPU::new(assemble(PROGRAM).unwrap());
+        let mut traced = cpu.traced(Vec::new());
+
+        traced.accept_input(4).unwrap();
+        assert_eq!(traced.run(), Ok(AwaitingInput));
+        traced.accept_input(1).unwrap();
+        assert_eq!(traced.run(), Ok(Halted));
+
+        let expected = "\
+# intcode-trace v1
+0\t0\tIN [13]\tin=4 w[13]=4
+1\t2\tARB #13\targs=13 rb=13
+2\t4\tADD [13], rb+1, rb+0\targs=4,3 w[13]=7
+3\t8\tOUT rb+0\targs=7 out=7
+4\t10\tIN [13]\twait
+5\t10\tIN [13]\tin=1 w[13]=1
+6\t12\tHLT\thalt
+";
+        let (_, out) = traced.into_inner();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_untraced() {
+        // Tracing doesn't change what the CPU does.
+        let program = assemble(PROGRAM).unwrap();
+        let mut plain = CPU::new(program.clone());
+        let mut traced = CPU::new(program).traced(std::io::sink());
+
+        for input in [4, 1] {
+            plain.accept_input(input).unwrap();
+            traced.accept_input(input).unwrap();
+            loop {
+                let state = plain.step();
+                assert_eq!(traced.step(), state);
+                if !matches!(state, Ok(IntcodeState::Continue) | Ok(OutputGenerated(_))) {
+                    break;
+                }
+            }
+        }
+
+        let cpu = &traced.cpu;
+        assert_eq!((cpu.instr_ptr, cpu.rel_base, cpu.steps, cpu.outputs), (plain.instr_ptr, plain.rel_base, plain.steps, plain.outputs));
+        assert_eq!(cpu.memory.get(13), plain.memory.get(13));
+    }
+}
@@ -0,0 +1,393 @@
+use crate::intcode::cpu::Instruction::*;
+use crate::intcode::cpu::{Instruction, Parameter, CPU};
+use crate::intcode::disasm::{disassemble, Line};
+use crate::intcode::IntcodeError::LogicError;
+use crate::intcode::IntcodeState::{AwaitingInput, Continue, Halted, OutOfSteps, OutputGenerated};
+use crate::intcode::{IntcodeResult, Runnable};
+use itertools::Itertools;
+
+/*
+   Translates a program into Rust source for a machine that implements `Runnable` and
+   `Resettable` like `CPU` does.  The machine keeps its state in an ordinary `CPU`; every
+   instruction found by the disassembler becomes a match arm that does the work with the modes
+   already resolved, and anything else (unreached code, bad instructions) is handed to the CPU.
+
+   A write into an address that holds translated code, whether by a translated instruction or one
+   the CPU runs, switches the machine over to the CPU for good, so self-modifying programs behave
+   exactly as they do under the interpreter.  Steps, outputs and limits are counted and checked
+   just as the CPU does it, so a transpiled machine can be given a budget and resumed.
+
+   `intcode` is the path the generated code uses for this module, e.g.
+   `adventofcode2019::intcode`.
+ */
+pub fn transpile(program: &[isize], name: &str, intcode: &str) -> String {
+    let code = disassemble(program).lines.into_iter()
+        .filter_map(|line| match line {
+            Line::Code { addr, instr, .. } => Some((addr, instr)),
+            Line::Data { .. } => None
+        })
+        .collect_vec();
+
+    let arms = code.iter()
+        .filter_map(|&(addr, instr)| arm(addr, instr))
+        .join("\n");
+
+    let ranges = code.iter()
+        .map(|&(addr, instr)| (addr, addr + instr.width() - 1))
+        .coalesce(|(a, b), (c, d)| if b + 1 == c { Ok((a, d)) } else { Err(((a, b), (c, d))) })
+        .map(|(a, b)| format!("{a}..={b}"))
+        .join(" | ");
+
+    let words = program.chunks(16)
+        .map(|chunk| format!("    {},", chunk.iter().join(", ")))
+        .join("\n");
+
+    let mut body = format!("\
+const PROGRAM: [isize; {len}] = [
+{words}
+];
+
+#[derive(Clone)]
+pub struct {name} {{
+    pub cpu: CPU,
+    interpreted: bool
+}}
+
+impl Default for {name} {{
+    fn default() -> {name} {{
+        {name}::new()
+    }}
+}}
+
+impl {name} {{
+    pub fn new() -> {name} {{
+        {name} {{ cpu: CPU::new(PROGRAM.to_vec()), interpreted: false }}
+    }}
+
+    pub fn interpreted(&self) -> bool {{
+        self.interpreted
+    }}
+
+    fn is_code(addr: isize) -> bool {{
+        matches!(addr, {ranges})
+    }}
+
+    fn fallback(&mut self) -> IntcodeResult<IntcodeState<isize>> {{
+        let target = self.cpu.cur_instr().ok()
+            .and_then(|instr| instr.target())
+            .and_then(|param| self.cpu.address(param));
+
+        let state = self.cpu.step()?;
+        if matches!(state, Continue) && target.is_some_and(Self::is_code) {{
+            self.interpreted = true;
+        }}
+        Ok(state)
+    }}
+", len = program.len());
+
+    if arms.contains("self.relative(") {
+        body.push_str("
+    fn relative(&self, offset: isize) -> IntcodeResult<isize> {
+        self.cpu.rel_base.checked_add(offset).ok_or(Overflow(self.cpu.instr_ptr))
+    }
+");
+    }
+
+    if arms.contains("self.store(") {
+        body.push_str("
+    fn store(&mut self, addr: isize, value: isize) -> IntcodeResult<()> {
+        let allocated = usize::try_from(addr).map(|a| self.cpu.memory.ram().allocated_with(a));
+        if let Some(max) = self.cpu.limits.max_memory.filter(|&max| allocated.is_ok_and(|n| n > max)) {
+            return Err(MemoryLimit(max));
+        }
+
+        if Self::is_code(addr) {
+            self.interpreted = true;
+        }
+        self.cpu.memory.set(addr, value)
+    }
+");
+    }
+
+    body.push_str(&format!("}}
+
+impl Runnable for {name} {{
+    type Input = isize;
+    type Output = isize;
+
+    fn accept_input(&mut self, input: isize) -> IntcodeResult<()> {{
+        self.cpu.accept_input(input)
+    }}
+
+    fn step(&mut self) -> IntcodeResult<IntcodeState<isize>> {{
+        if self.interpreted {{
+            return self.cpu.step();
+        }}
+
+        if self.cpu.limits.max_steps.is_some_and(|max| self.cpu.steps >= max) {{
+            return Ok(OutOfSteps);
+        }}
+
+        let state = match self.cpu.instr_ptr {{
+{arms}
+            _ => return self.fallback()
+        }}?;
+
+        self.cpu.steps += 1;
+        Ok(state)
+    }}
+}}
+
+impl Resettable for {name} {{
+    fn reset(&mut self) {{
+        self.cpu.reset();
+        self.interpreted = false;
+    }}
+}}
+"));
+
+    // Only import what the translated instructions actually use.
+    let states = ["AwaitingInput", "Continue", "Halted", "OutOfSteps", "OutputGenerated"].into_iter()
+        .filter(|state| body.contains(state))
+        .join(", ");
+
+    let mut imports = vec![format!("use {intcode}::cpu::CPU;")];
+    if body.contains("allocated_with(") {
+        imports.push(format!("use {intcode}::memory::Storage;"));
+    }
+    if body.contains("_with(") {
+        imports.push(format!("use {intcode}::word::Word;"));
+    }
+
+    let errors = ["MemoryLimit", "OutputLimit", "Overflow"].into_iter()
+        .filter(|error| body.contains(&format!("{error}(")))
+        .collect_vec();
+
+    match errors[..] {
+        [] => (),
+        [error] => imports.push(format!("use {intcode}::IntcodeError::{error};")),
+        _ => imports.push(format!("use {intcode}::IntcodeError::{{{}}};", errors.join(", ")))
+    }
+    if !states.is_empty() {
+        imports.push(format!("use {intcode}::IntcodeState::{{{states}}};"));
+    }
+    imports.push(format!("use {intcode}::{{IntcodeResult, IntcodeState, Resettable, Runnable}};"));
+
+    format!("// Generated by intcode::transpile; do not edit.\n\n{}\n\n{body}", imports.join("\n"))
+}
+
+fn read(param: Parameter) -> String {
+    match param {
+        Parameter::Position(addr) => format!("self.cpu.memory.get({addr})?"),
+        Parameter::Immediate(val) => format!("{val}"),
+        Parameter::Relative(offset) => format!("self.cpu.memory.get(self.relative({offset})?)?")
+    }
+}
+
+fn write(param: Parameter, value: &str) -> Option<String> {
+    match param {
+        Parameter::Position(addr) => Some(format!("self.store({addr}, {value})?;")),
+        Parameter::Immediate(_) => None,
+        Parameter::Relative(offset) => Some(format!("self.store(self.relative({offset})?, {value})?;"))
+    }
+}
+
+// The body of the match arm for one instruction, or None to leave it to the interpreter.
+fn arm(addr: isize, instr: Instruction) -> Option<String> {
+    let next = addr + instr.width();
+
+    let body = match instr {
+        Add(p1, p2, p3) | Multiply(p1, p2, p3) => {
+            let op = if let Add(..) = instr { "add_with" } else { "mul_with" };
+            vec![
+                format!("let a: isize = {};", read(p1)),
+                format!("let b: isize = {};", read(p2)),
+                format!("let r = a.{op}(&b, self.cpu.arithmetic).ok_or(Overflow({addr}))?;"),
+                write(p3, "r")?,
+                format!("self.cpu.instr_ptr = {next};"),
+                "Ok(Continue)".to_string()
+            ]
+        },
+        LessThan(p1, p2, p3) | Equal(p1, p2, p3) => {
+            let op = if let LessThan(..) = instr { "<" } else { "==" };
+            vec![
+                format!("let a: isize = {};", read(p1)),
+                format!("let b: isize = {};", read(p2)),
+                write(p3, &format!("isize::from(a {op} b)"))?,
+                format!("self.cpu.instr_ptr = {next};"),
+                "Ok(Continue)".to_string()
+            ]
+        },
+        Input(p1) => vec![
+            "let Some(v) = self.cpu.input.take() else { return Ok(AwaitingInput) };".to_string(),
+            write(p1, "v")?,
+            format!("self.cpu.instr_ptr = {next};"),
+            "Ok(Continue)".to_string()
+        ],
+        Output(p1) => vec![
+            "if let Some(max) = self.cpu.limits.max_outputs.filter(|&max| self.cpu.outputs >= max) {".to_string(),
+            "    return Err(OutputLimit(max));".to_string(),
+            "}".to_string(),
+            format!("let a: isize = {};", read(p1)),
+            "self.cpu.outputs += 1;".to_string(),
+            format!("self.cpu.instr_ptr = {next};"),
+            "Ok(OutputGenerated(a))".to_string()
+        ],
+        JumpIfTrue(p1, p2) | JumpIfFalse(p1, p2) => {
+            let op = if let JumpIfTrue(..) = instr { "!=" } else { "==" };
+            vec![
+                format!("let a: isize = {};", read(p1)),
+                format!("self.cpu.instr_ptr = if a {op} 0 {{ {} }} else {{ {next} }};", read(p2)),
+                "Ok(Continue)".to_string()
+            ]
+        },
+        RelativeBaseOffset(p1) => vec![
+            format!("let a: isize = {};", read(p1)),
+            format!("self.cpu.rel_base = self.cpu.rel_base.add_with(&a, self.cpu.arithmetic).ok_or(Overflow({addr}))?;"),
+            format!("self.cpu.instr_ptr = {next};"),
+            "Ok(Continue)".to_string()
+        ],
+        Done => vec!["Ok(Halted)".to_string()]
+    };
+
+    let body = body.iter().map(|line| format!("                {line}")).join("\n");
+    Some(format!("            {addr} => {{\n{body}\n            }},"))
+}
+
+/*
+   Runs `machine` and the interpreter side by side, feeding both the same inputs, and fails at
+   the first step where they disagree.  Returns the outputs once the program halts or wants more
+   input than was given.
+ */
+pub fn verify<R>(mut machine: R, mut reference: CPU, inputs: &[isize]) -> IntcodeResult<Vec<isize>>
+where R: Runnable<Input=isize, Output=isize> {
+    let mut inputs = inputs.iter().copied();
+    let mut outputs = Vec::new();
+    let mut step = 0;
+
+    loop {
+        let expected = reference.step();
+        let actual = machine.step();
+
+        // Only the interpreter knows where it was when it failed.
+        let agree = match (&expected, &actual) {
+            (Err(e), Err(a)) => e.cause() == a.cause(),
+            _ => expected == actual
+        };
+
+        if !agree {
+            return Err(LogicError(format!("Step {step}: expected {expected:?}, got {actual:?}")));
+        }
+
+        match expected? {
+            OutputGenerated(o) => outputs.push(o),
+            AwaitingInput => match inputs.next() {
+                Some(i) => {
+                    reference.accept_input(i)?;
+                    machine.accept_input(i)?;
+                },
+                None => return Ok(outputs)
+            },
+            Halted | OutOfSteps => return Ok(outputs),
+            Continue => ()
+        }
+
+        step += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+    use crate::intcode::cpu::Limits;
+    use crate::intcode::IntcodeError::OutputLimit;
+    use crate::intcode::Resettable;
+
+    mod countdown {
+        include!("testdata/countdown.rs");
+    }
+
+    mod patch {
+        include!("testdata/patch.rs");
+    }
+
+    const COUNTDOWN: &str = "
+            IN [n]
+            ARB #100
+    loop:   OUT [n]
+            ADD rb+0, [n], rb+0
+            ADD [n], #-1, [n]
+            JT [n], #loop
+            OUT rb+0
+            ADD #0, #99, [patch]    ; turns the next instruction into HLT
+    patch:  OUT #7
+            HLT
+    n:      .data 0
+    ";
+
+    #[test]
+    fn test_transpile() {
+        let program = assemble(COUNTDOWN).unwrap();
+        let source = transpile(&program, "Countdown", "crate::intcode");
+        assert_eq!(source, include_str!("testdata/countdown.rs"));
+
+        let machine = countdown::Countdown::new();
+        let outputs = verify(machine.clone(), CPU::new(program.clone()), &[3]);
+        assert_eq!(outputs, Ok(vec![3, 2, 1, 6]));
+
+        let mut machine = machine;
+        machine.accept_input(2).unwrap();
+        assert_eq!(machine.run(), Ok(Halted));
+        assert!(machine.interpreted());
+        machine.reset();
+        assert!(!machine.interpreted());
+    }
+
+    #[test]
+    fn test_fallback_writes_into_code() {
+        let program = assemble("
+                    JT #1, [target]         ; an indirect jump, so `poke` isn't translated
+            back:   OUT #5
+                    HLT
+            poke:   ADD #0, #9, [back+1]    ; turns the OUT #5 into OUT #9
+                    JT #1, #back
+            target: .data poke
+        ").unwrap();
+        assert_eq!(transpile(&program, "Patch", "crate::intcode"), include_str!("testdata/patch.rs"));
+
+        let outputs = verify(patch::Patch::new(), CPU::new(program), &[]);
+        assert_eq!(outputs, Ok(vec![9]));
+
+        let mut machine = patch::Patch::new();
+        assert_eq!(machine.run_until_output(), Ok(9));
+        assert!(machine.interpreted());
+    }
+
+    #[test]
+    fn test_limits() {
+        let program = assemble(COUNTDOWN).unwrap();
+        let limits = Limits { max_steps: Some(5), ..Limits::default() };
+
+        let mut machine = countdown::Countdown::new();
+        machine.cpu.limits = limits;
+        machine.accept_input(3).unwrap();
+        assert_eq!(machine.run_until_output(), Ok(3));
+        assert_eq!(machine.run(), Ok(OutOfSteps));
+        assert_eq!((machine.cpu.steps, machine.cpu.outputs, machine.cpu.instr_ptr), (5, 1, 14));
+
+        machine.cpu.allow_steps(2);
+        assert_eq!(machine.run_until_output(), Ok(2));
+        assert_eq!(machine.step(), Ok(OutOfSteps));
+
+        // Stops on the same step as the interpreter.
+        let mut machine = countdown::Countdown::new();
+        machine.cpu.limits = limits;
+        assert_eq!(verify(machine, CPU::new(program).with_limits(limits), &[3]), Ok(vec![3]));
+
+        let mut machine = countdown::Countdown::new();
+        machine.cpu.limits = Limits { max_outputs: Some(2), ..Limits::default() };
+        machine.accept_input(3).unwrap();
+        assert_eq!(machine.run(), Err(OutputLimit(2)));
+    }
+}
@@ -0,0 +1,50 @@
+use crate::intcode::cpu::Arithmetic;
+use num::{BigInt, FromPrimitive, Integer, Signed, ToPrimitive};
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+
+// The integer type a CPU computes with.  Addresses, the instruction pointer and the relative base
+// are always isize; words used as addresses must fit in one.
+pub trait Word: Clone + Debug + Display + Eq + Ord + Hash + Integer + Signed + FromPrimitive + ToPrimitive
+    + Send + Sync + 'static {
+    fn add_with(&self, other: &Self, arithmetic: Arithmetic) -> Option<Self>;
+
+    fn mul_with(&self, other: &Self, arithmetic: Arithmetic) -> Option<Self>;
+}
+
+macro_rules! primitive_word {
+    ($($t:ty),+) => {
+        $(
+        impl Word for $t {
+            fn add_with(&self, other: &$t, arithmetic: Arithmetic) -> Option<$t> {
+                match arithmetic {
+                    Arithmetic::Checked => self.checked_add(*other),
+                    Arithmetic::Wrapping => Some(self.wrapping_add(*other)),
+                    Arithmetic::Saturating => Some(self.saturating_add(*other))
+                }
+            }
+
+            fn mul_with(&self, other: &$t, arithmetic: Arithmetic) -> Option<$t> {
+                match arithmetic {
+                    Arithmetic::Checked => self.checked_mul(*other),
+                    Arithmetic::Wrapping => Some(self.wrapping_mul(*other)),
+                    Arithmetic::Saturating => Some(self.saturating_mul(*other))
+                }
+            }
+        }
+        )+
+    };
+}
+
+primitive_word!(i32, i64, i128, isize);
+
+// A BigInt never overflows, so every policy behaves the same.
+impl Word for BigInt {
+    fn add_with(&self, other: &BigInt, _: Arithmetic) -> Option<BigInt> {
+        Some(self + other)
+    }
+
+    fn mul_with(&self, other: &BigInt, _: Arithmetic) -> Option<BigInt> {
+        Some(self * other)
+    }
+}
@@ -26,12 +26,22 @@ macro_rules! build_main_res {
         use std::time::Instant;
         fn main() {
             let input: &str = include_str!(concat!("../input/", $input));
+            let mut failed = false;
             $(
             let start = Instant::now();
-            let result = $solver(input).unwrap();
+            let result = $solver(input);
             let duration = start.elapsed().as_micros();
-            println!("{}: {} (Time: {}μs)", $part, result, duration);
+            match result {
+                Ok(result) => println!("{}: {} (Time: {}μs)", $part, result, duration),
+                Err(e) => {
+                    eprintln!("{}: {}", $part, e);
+                    failed = true;
+                }
+            }
             )+
+            if failed {
+                std::process::exit(1);
+            }
         }
     };
 }